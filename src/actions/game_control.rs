@@ -1,28 +1,174 @@
-use bevy::prelude::{Input, KeyCode, Res};
+use bevy::prelude::{GamepadButtonType, KeyCode, MouseButton};
+use serde::{Deserialize, Serialize};
+
+use crate::actions::input_map::{Binding, ControlInput};
 use crate::dog::DogCommand;
 
+// Everything the player can do with a key or button, see `InputMap` for what is bound to each
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum GameControl {
-    Up,
-    Down,
-    Left,
-    Right,
+    // A command for the dogs on a set of keys
+    Command(CommandKeys, DogCommand),
+    // Pick out a dog by its number, counting from 0
+    SelectDog(usize),
+    NextDog,
+    Gate,
+    CameraMode,
+    ZoomIn,
+    ZoomOut,
+    // Held while moving the mouse to drag the view around
+    PanCamera,
+    // Sends the dog round the flock to where the pointer is, dragging sets how wide it works
+    PointDog,
+    HandOver,
+    Pause,
+}
+
+// Which keys give a dog its commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum CommandKeys {
+    // The arrows (or WASD) and letters, for whichever dog is selected
+    #[default]
+    Main,
+    // The number pad, so a second dog can be worked without switching over to it
+    Numpad,
+}
+
+impl CommandKeys {
+    pub const ALL: [CommandKeys; 2] = [CommandKeys::Main, CommandKeys::Numpad];
 }
 
+// 1 to 9 pick out a dog by its number
+static SELECT_DOG_KEYS: [KeyCode; 9] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+
 impl GameControl {
-    pub fn pressed(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
+    // Every control, in the order the rebinding screen lists them
+    pub fn all() -> Vec<GameControl> {
+        let mut controls = Vec::new();
+        for keys in CommandKeys::ALL {
+            controls.extend(DogCommand::ALL.into_iter().map(|command| GameControl::Command(keys, command)));
+        }
+        controls.extend((0..SELECT_DOG_KEYS.len()).map(GameControl::SelectDog));
+        controls.extend([
+            GameControl::NextDog,
+            GameControl::Gate,
+            GameControl::CameraMode,
+            GameControl::ZoomIn,
+            GameControl::ZoomOut,
+            GameControl::PanCamera,
+            GameControl::PointDog,
+            GameControl::HandOver,
+            GameControl::Pause,
+        ]);
+        controls
+    }
+
+    pub fn name(&self) -> String {
         match self {
-            GameControl::Up => {
-                keyboard_input.pressed(KeyCode::W) || keyboard_input.pressed(KeyCode::Up)
-            }
-            GameControl::Down => {
-                keyboard_input.pressed(KeyCode::S) || keyboard_input.pressed(KeyCode::Down)
-            }
-            GameControl::Left => {
-                keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left)
-            }
-            GameControl::Right => {
-                keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right)
-            }
+            GameControl::Command(CommandKeys::Main, command) => command.name().to_string(),
+            GameControl::Command(CommandKeys::Numpad, command) => format!("{} (second dog)", command.name()),
+            GameControl::SelectDog(number) => format!("Select dog {}", number + 1),
+            GameControl::NextDog => "Next dog".to_string(),
+            GameControl::Gate => "Open or shut the gate".to_string(),
+            GameControl::CameraMode => "Camera mode".to_string(),
+            GameControl::ZoomIn => "Zoom in".to_string(),
+            GameControl::ZoomOut => "Zoom out".to_string(),
+            GameControl::PanCamera => "Drag the view".to_string(),
+            GameControl::PointDog => "Send the dog to a point".to_string(),
+            GameControl::HandOver => "Hand over to the computer".to_string(),
+            GameControl::Pause => "Pause".to_string(),
+        }
+    }
+
+    // What the control is bound to out of the box
+    pub fn default_bindings(&self) -> Vec<Binding> {
+        let mut bindings: Vec<Binding> = self.default_keys().iter().copied().map(Binding::Key).collect();
+        bindings.extend(self.default_mouse_button().map(Binding::Mouse));
+        bindings.extend(self.default_gamepad_button().map(Binding::Gamepad));
+        bindings
+    }
+
+    fn default_keys(&self) -> &'static [KeyCode] {
+        use DogCommand::*;
+
+        match self {
+            GameControl::Command(CommandKeys::Main, command) => match command {
+                WalkOn => &[KeyCode::W, KeyCode::Up],
+                LayDown => &[KeyCode::S, KeyCode::Down],
+                ComeBye => &[KeyCode::A, KeyCode::Left],
+                Away => &[KeyCode::D, KeyCode::Right],
+                Easy => &[KeyCode::E],
+                Steady => &[KeyCode::F],
+                ThatllDo => &[KeyCode::T],
+                LookBack => &[KeyCode::L],
+                GetOut => &[KeyCode::G],
+                Here => &[KeyCode::H],
+            },
+            // Laid out like the main keys: 8 4 2 6 for walk on, come bye, lie down and away, the corners and 5 for the rest
+            GameControl::Command(CommandKeys::Numpad, command) => match command {
+                WalkOn => &[KeyCode::Numpad8],
+                LayDown => &[KeyCode::Numpad2],
+                ComeBye => &[KeyCode::Numpad4],
+                Away => &[KeyCode::Numpad6],
+                Easy => &[KeyCode::Numpad7],
+                Steady => &[KeyCode::Numpad9],
+                ThatllDo => &[KeyCode::Numpad1],
+                LookBack => &[KeyCode::Numpad3],
+                GetOut => &[KeyCode::Numpad0],
+                Here => &[KeyCode::Numpad5],
+            },
+            GameControl::SelectDog(number) => SELECT_DOG_KEYS.get(*number..*number + 1).unwrap_or_default(),
+            GameControl::NextDog => &[KeyCode::Tab],
+            GameControl::Gate => &[KeyCode::Space],
+            GameControl::CameraMode => &[KeyCode::C],
+            GameControl::ZoomIn => &[KeyCode::Equals, KeyCode::NumpadAdd],
+            GameControl::ZoomOut => &[KeyCode::Minus, KeyCode::NumpadSubtract],
+            GameControl::PanCamera | GameControl::PointDog => &[],
+            GameControl::HandOver => &[KeyCode::I],
+            GameControl::Pause => &[KeyCode::P],
+        }
+    }
+
+    fn default_mouse_button(&self) -> Option<MouseButton> {
+        match self {
+            GameControl::PanCamera => Some(MouseButton::Right),
+            GameControl::PointDog => Some(MouseButton::Left),
+            _ => None,
+        }
+    }
+
+    // Flanking is on the left stick and the triggers set the pace, see `get_gamepad_command`.
+    // Bevy calls the bumpers `LeftTrigger` and `RightTrigger`, the analog triggers are the `2`s.
+    fn default_gamepad_button(&self) -> Option<GamepadButtonType> {
+        use DogCommand::*;
+
+        match self {
+            GameControl::Command(CommandKeys::Main, command) => match command {
+                LayDown => Some(GamepadButtonType::South),
+                WalkOn => Some(GamepadButtonType::North),
+                ThatllDo => Some(GamepadButtonType::East),
+                Here => Some(GamepadButtonType::West),
+                LookBack => Some(GamepadButtonType::DPadUp),
+                GetOut => Some(GamepadButtonType::DPadDown),
+                Easy => Some(GamepadButtonType::DPadLeft),
+                Steady => Some(GamepadButtonType::DPadRight),
+                ComeBye | Away => None,
+            },
+            GameControl::NextDog => Some(GamepadButtonType::LeftTrigger),
+            GameControl::Gate => Some(GamepadButtonType::RightTrigger),
+            GameControl::CameraMode => Some(GamepadButtonType::Select),
+            GameControl::Pause => Some(GamepadButtonType::Start),
+            _ => None,
         }
     }
 }
@@ -35,16 +181,20 @@ impl GameControl {
 //     }
 // }
 
-pub fn get_command_for_input(input: &Res<Input<KeyCode>>) -> Option<DogCommand> {
-    if GameControl::Down.pressed(input) {
-        Some(DogCommand::LayDown)
-    } else if GameControl::Right.pressed(input) {
-        Some(DogCommand::Away)
-    } else if GameControl::Left.pressed(input) {
-        Some(DogCommand::ComeBye)
-    } else if GameControl::Up.pressed(input) {
-        Some(DogCommand::WalkOn)
-    } else {
-        None
-    }
+// The first command held down on a set of keys, in the order of `DogCommand::ALL`
+pub fn get_command_for_input(input: &ControlInput, keys: CommandKeys) -> Option<DogCommand> {
+    DogCommand::ALL.into_iter().find(|command| {
+        let control = GameControl::Command(keys, *command);
+        if *command == DogCommand::GetOut {
+            // Each press widens the flank another step, so holding the key shouldn't keep widening it
+            input.just_pressed(control)
+        } else {
+            input.pressed(control)
+        }
+    })
+}
+
+// The dog picked by number this frame
+pub fn get_dog_selection(input: &ControlInput) -> Option<usize> {
+    (0..SELECT_DOG_KEYS.len()).find(|number| input.just_pressed(GameControl::SelectDog(*number)))
 }
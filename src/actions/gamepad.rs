@@ -0,0 +1,90 @@
+use bevy::prelude::*;
+
+use crate::ai_handler::AiHandler;
+use crate::dog::{flank_towards, Dog, HandlerPost, SelectedDog};
+use crate::sheep::Flock;
+
+// How far the left stick has to be pushed before it counts
+const STICK_DEAD_ZONE: f32 = 0.25;
+// Pace with the stick only just past the dead zone, rising to full speed with it pushed all the way
+const MIN_STICK_PACE: f32 = 0.3;
+// Once the dog is within this angle (in radians) of where the stick points it lies down there
+const STICK_ARRIVE_ANGLE: f32 = 0.15;
+// How quickly a fully pulled trigger changes the pace, in full speed per second
+const TRIGGER_PACE_RATE: f32 = 0.5;
+// Triggers resting a little off zero shouldn't creep the pace
+const TRIGGER_DEAD_ZONE: f32 = 0.1;
+const MIN_TRIGGER_PACE: f32 = 0.1;
+// Smallest change of pace worth telling the dog, a stick or trigger never rests exactly still
+const PACE_STEP: f32 = 0.05;
+
+// The left stick points at where round the flock the selected dog should be. The dog flanks the
+// short way there, come bye or away, at a pace set by how far the stick is pushed, and lies down
+// once it arrives. With the stick at rest the left trigger eases the dog off and the right one
+// pushes it on, the harder the pull the faster the change.
+#[allow(clippy::too_many_arguments)]
+pub fn get_gamepad_command(
+    time: Res<Time>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    button_axes: Res<Axis<GamepadButton>>,
+    flock: Res<Flock>,
+    selected: Res<SelectedDog>,
+    handler_post: Res<HandlerPost>,
+    mut dog_query: Query<(&Transform, &mut Dog), Without<AiHandler>>,
+    // The pace the triggers are winding towards, while they are pulled
+    mut trigger_pace: Local<Option<f32>>,
+) {
+    let axis = |gamepad: Gamepad, axis_type: GamepadAxisType| axes.get(GamepadAxis::new(gamepad, axis_type)).unwrap_or(0.);
+    let trigger = |gamepad: Gamepad, button_type: GamepadButtonType| {
+        let pull = button_axes.get(GamepadButton::new(gamepad, button_type)).unwrap_or(0.);
+        if pull > TRIGGER_DEAD_ZONE { pull } else { 0. }
+    };
+
+    // Whichever pad is being used the most, if there are several
+    let Some((stick, triggers)) = gamepads
+        .iter()
+        .map(|gamepad| {
+            let stick = Vec2::new(axis(gamepad, GamepadAxisType::LeftStickX), axis(gamepad, GamepadAxisType::LeftStickY));
+            let triggers = trigger(gamepad, GamepadButtonType::RightTrigger2) - trigger(gamepad, GamepadButtonType::LeftTrigger2);
+            (stick, triggers)
+        })
+        .max_by(|a, b| (a.0.length() + a.1.abs()).total_cmp(&(b.0.length() + b.1.abs())))
+    else {
+        return;
+    };
+
+    let Some((transform, mut dog)) = dog_query.iter_mut().find(|(_, dog)| dog.number() == selected.0) else {
+        return;
+    };
+    let position = transform.translation.truncate();
+    if !dog.hears(position, handler_post.0) {
+        return;
+    }
+
+    let deflection = stick.length();
+    if deflection > STICK_DEAD_ZONE && flock.count > 0 {
+        // The view is never rotated, so up on the stick is up the field
+        let command = flank_towards(position - flock.centre, stick, STICK_ARRIVE_ANGLE);
+        let push = ((deflection - STICK_DEAD_ZONE) / (1. - STICK_DEAD_ZONE)).clamp(0., 1.);
+        let pace = MIN_STICK_PACE + (1. - MIN_STICK_PACE) * push;
+
+        // A new flank puts the dog back to full speed, so the stick's pace goes on after it
+        if *dog.command() != command {
+            dog.call(&command, position, handler_post.0);
+            dog.set_pace(pace);
+        } else if (dog.pace() - pace).abs() >= PACE_STEP {
+            dog.set_pace(pace);
+        }
+        *trigger_pace = None;
+    } else if triggers != 0. {
+        let pace = trigger_pace.unwrap_or(dog.pace()) + triggers * TRIGGER_PACE_RATE * time.delta_seconds();
+        let pace = pace.clamp(MIN_TRIGGER_PACE, 1.);
+        *trigger_pace = Some(pace);
+        if (dog.pace() - pace).abs() >= PACE_STEP || pace == MIN_TRIGGER_PACE || pace == 1. {
+            dog.set_pace(pace);
+        }
+    } else {
+        *trigger_pace = None;
+    }
+}
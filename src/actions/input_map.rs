@@ -0,0 +1,161 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::actions::game_control::GameControl;
+
+// Where the player's bindings are kept between runs, next to wherever the game is started from
+#[cfg(not(target_arch = "wasm32"))]
+const INPUT_MAP_PATH: &str = "input.ron";
+
+// A key or button that can be bound to a control
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    // A button on any connected gamepad
+    Gamepad(GamepadButtonType),
+}
+
+impl Binding {
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(button) => format!("Mouse {:?}", button),
+            Binding::Gamepad(button) => format!("Pad {:?}", button),
+        }
+    }
+
+    // Any key or button that went down this frame, for binding to a control
+    pub fn just_pressed(
+        keys: &Input<KeyCode>,
+        mouse_buttons: &Input<MouseButton>,
+        gamepad_buttons: &Input<GamepadButton>,
+    ) -> Option<Self> {
+        keys.get_just_pressed()
+            .next()
+            .map(|key| Binding::Key(*key))
+            .or_else(|| mouse_buttons.get_just_pressed().next().map(|button| Binding::Mouse(*button)))
+            .or_else(|| {
+                gamepad_buttons
+                    .get_just_pressed()
+                    .next()
+                    .map(|button| Binding::Gamepad(button.button_type))
+            })
+    }
+}
+
+// What every control is bound to. Insert one before the game plugins to pick the bindings, otherwise
+// they are loaded from the player's saved bindings or else the defaults.
+#[derive(Resource, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputMap {
+    bindings: Vec<(GameControl, Vec<Binding>)>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        Self {
+            bindings: GameControl::all()
+                .into_iter()
+                .map(|control| (control, control.default_bindings()))
+                .collect(),
+        }
+    }
+}
+
+impl InputMap {
+    pub fn bindings(&self, control: GameControl) -> &[Binding] {
+        self.bindings
+            .iter()
+            .find(|(bound, _)| *bound == control)
+            .map(|(_, bindings)| bindings.as_slice())
+            .unwrap_or_default()
+    }
+
+    // Makes `binding` the only one for `control`, taking it off any other control that had it
+    pub fn bind(&mut self, control: GameControl, binding: Binding) {
+        for (_, bindings) in &mut self.bindings {
+            bindings.retain(|bound| *bound != binding);
+        }
+        match self.bindings.iter_mut().find(|(bound, _)| *bound == control) {
+            Some((_, bindings)) => *bindings = vec![binding],
+            None => self.bindings.push((control, vec![binding])),
+        }
+    }
+
+    // The saved bindings, with the defaults for any control added since they were saved
+    pub fn load() -> Self {
+        let mut input_map = Self::read().unwrap_or_default();
+        for (control, bindings) in Self::default().bindings {
+            if !input_map.bindings.iter().any(|(bound, _)| *bound == control) {
+                input_map.bindings.push((control, bindings));
+            }
+        }
+        input_map
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn read() -> Option<Self> {
+        let saved = std::fs::read_to_string(INPUT_MAP_PATH).ok()?;
+        match ron::from_str(&saved) {
+            Ok(input_map) => Some(input_map),
+            Err(error) => {
+                warn!("Ignoring the saved bindings in {}: {}", INPUT_MAP_PATH, error);
+                None
+            }
+        }
+    }
+
+    // The web build has nowhere to keep them
+    #[cfg(target_arch = "wasm32")]
+    fn read() -> Option<Self> {
+        None
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self) {
+        let saved = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| error.to_string())
+            .and_then(|saved| std::fs::write(INPUT_MAP_PATH, saved).map_err(|error| error.to_string()));
+        if let Err(error) = saved {
+            warn!("Couldn't save the bindings to {}: {}", INPUT_MAP_PATH, error);
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn save(&self) {}
+}
+
+// The state of every key and button, read through the input map
+#[derive(SystemParam)]
+pub struct ControlInput<'w> {
+    input_map: Res<'w, InputMap>,
+    keys: Res<'w, Input<KeyCode>>,
+    mouse_buttons: Res<'w, Input<MouseButton>>,
+    gamepad_buttons: Res<'w, Input<GamepadButton>>,
+    gamepads: Res<'w, Gamepads>,
+}
+
+impl ControlInput<'_> {
+    pub fn pressed(&self, control: GameControl) -> bool {
+        self.input_map.bindings(control).iter().any(|binding| match binding {
+            Binding::Key(key) => self.keys.pressed(*key),
+            Binding::Mouse(button) => self.mouse_buttons.pressed(*button),
+            Binding::Gamepad(button) => self
+                .gamepads
+                .iter()
+                .any(|gamepad| self.gamepad_buttons.pressed(GamepadButton::new(gamepad, *button))),
+        })
+    }
+
+    pub fn just_pressed(&self, control: GameControl) -> bool {
+        self.input_map.bindings(control).iter().any(|binding| match binding {
+            Binding::Key(key) => self.keys.just_pressed(*key),
+            Binding::Mouse(button) => self.mouse_buttons.just_pressed(*button),
+            Binding::Gamepad(button) => self
+                .gamepads
+                .iter()
+                .any(|gamepad| self.gamepad_buttons.just_pressed(GamepadButton::new(gamepad, *button))),
+        })
+    }
+}
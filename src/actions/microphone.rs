@@ -0,0 +1,93 @@
+use std::sync::{Arc, Mutex};
+
+use bevy::prelude::*;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SizedSample};
+
+use crate::actions::whistle::AudioInput;
+
+// The default input device, recording for as long as the game runs. The stream fills the buffer
+// from its own thread, and it is emptied into `AudioInput` events every frame.
+pub struct Microphone {
+    // Kept so the stream isn't stopped, it is never read
+    _stream: cpal::Stream,
+    sample_rate: u32,
+    buffer: Arc<Mutex<Vec<f32>>>,
+}
+
+// Starts listening, without a microphone the game just carries on with whistles left out
+pub fn start_microphone(world: &mut World) {
+    match open_microphone() {
+        Ok(microphone) => {
+            info!("Listening for whistles at {} Hz", microphone.sample_rate);
+            world.insert_non_send_resource(microphone);
+        }
+        Err(error) => warn!("Not listening for whistles, couldn't open the microphone: {}", error),
+    }
+}
+
+fn open_microphone() -> Result<Microphone, String> {
+    let device = cpal::default_host()
+        .default_input_device()
+        .ok_or_else(|| "there is no input device".to_string())?;
+    let supported = device.default_input_config().map_err(|error| error.to_string())?;
+    let config = supported.config();
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+
+    let stream = match supported.sample_format() {
+        cpal::SampleFormat::F32 => build_stream::<f32>(&device, &config, buffer.clone()),
+        cpal::SampleFormat::I16 => build_stream::<i16>(&device, &config, buffer.clone()),
+        cpal::SampleFormat::I32 => build_stream::<i32>(&device, &config, buffer.clone()),
+        cpal::SampleFormat::U16 => build_stream::<u16>(&device, &config, buffer.clone()),
+        format => return Err(format!("samples in {} aren't supported", format)),
+    }
+    .map_err(|error| error.to_string())?;
+    stream.play().map_err(|error| error.to_string())?;
+
+    Ok(Microphone {
+        _stream: stream,
+        sample_rate: config.sample_rate.0,
+        buffer,
+    })
+}
+
+// Mixes every channel down to one as the samples come in
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    buffer: Arc<Mutex<Vec<f32>>>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let channels = config.channels.max(1) as usize;
+    device.build_input_stream(
+        config,
+        move |data: &[T], _: &cpal::InputCallbackInfo| {
+            if let Ok(mut buffer) = buffer.lock() {
+                buffer.extend(data.chunks(channels).map(|frame| {
+                    frame.iter().map(|sample| sample.to_sample::<f32>()).sum::<f32>() / frame.len() as f32
+                }));
+            }
+        },
+        |error| warn!("Microphone error: {}", error),
+        None,
+    )
+}
+
+pub fn read_microphone(microphone: Option<NonSend<Microphone>>, mut audio_events: EventWriter<AudioInput>) {
+    let Some(microphone) = microphone else {
+        return;
+    };
+    let samples = match microphone.buffer.lock() {
+        Ok(mut buffer) => std::mem::take(&mut *buffer),
+        Err(_) => return,
+    };
+    if !samples.is_empty() {
+        audio_events.send(AudioInput {
+            samples,
+            sample_rate: microphone.sample_rate,
+        });
+    }
+}
@@ -1,74 +1,161 @@
-use bevy::math::Vec3Swizzles;
+use bevy::input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel};
 use bevy::prelude::*;
 
-use crate::actions::game_control::{get_command_for_input};
-use crate::dog::Dog;
+use crate::actions::game_control::{get_command_for_input, get_dog_selection};
+use crate::actions::gamepad::get_gamepad_command;
+use crate::actions::mouse::get_mouse_target;
+use crate::actions::touch::get_touch_command;
+use crate::actions::whistle::get_whistle_command;
+use crate::actions::input_map::ControlInput;
+use crate::ai_handler::AiHandler;
+use crate::dog::{Dog, HandlerPost, SelectedDog};
+use crate::pen::GateCommand;
+use crate::replay::not_replaying;
 use crate::GameState;
 
 mod game_control;
+mod gamepad;
+mod input_map;
+#[cfg(feature = "microphone")]
+mod microphone;
+mod mouse;
+mod touch;
+mod whistle;
 
-pub const FOLLOW_EPSILON: f32 = 5.;
+pub use game_control::{CommandKeys, GameControl};
+pub use input_map::{Binding, InputMap};
+pub use touch::GestureRecogniser;
+pub use whistle::{
+    read_wav, recognise_recording, recognise_wav, AudioInput, WhistleNote, WhistleRecogniser, WhistleTemplate,
+    WhistleTemplates,
+};
 
 pub struct ActionsPlugin;
 
-// This plugin listens for keyboard input and converts the input into Actions
-// Actions can then be used as a resource in other systems to act on the player input.
+// This plugin listens for keyboard, mouse, gamepad, touch and whistled input and converts the input into Actions
+// through the InputMap. Actions can then be used as a resource in other systems to act on the player input.
 impl Plugin for ActionsPlugin {
     fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<InputMap>() {
+            app.insert_resource(InputMap::load());
+        }
+        if !app.world.contains_resource::<WhistleTemplates>() {
+            app.insert_resource(WhistleTemplates::load());
+        }
+
         app.init_resource::<Actions>()
-            // .add_systems(
-            // Update,
-            // set_movement_actions.run_if(in_state(GameState::Playing)),
-            .add_systems(Update, get_dog_command.run_if(in_state(GameState::Playing))
-        );
+            .init_resource::<GestureRecogniser>()
+            .init_resource::<WhistleRecogniser>()
+            .add_event::<AudioInput>()
+            .add_systems(Update, (select_dog, set_camera_actions, set_pause_actions).run_if(in_state(GameState::Playing)))
+            // A replay gives the dogs and gates their orders instead
+            .add_systems(Update, (get_dog_command, get_gate_command, set_handler_actions).run_if(in_state(GameState::Playing).and_then(not_replaying)))
+            // The stick takes over from buttons held at the same time
+            .add_systems(Update, (get_gamepad_command, get_touch_command, get_mouse_target, get_whistle_command).after(get_dog_command).run_if(in_state(GameState::Playing).and_then(not_replaying)));
+
+        #[cfg(feature = "microphone")]
+        {
+            app.add_systems(Startup, microphone::start_microphone)
+                .add_systems(Update, microphone::read_microphone.before(get_whistle_command));
+        }
     }
 }
 
 #[derive(Default, Resource)]
 pub struct Actions {
     pub player_movement: Option<Vec2>,
+    // Zoom steps this frame, positive to zoom in
+    pub camera_zoom: f32,
+    // How far the view was dragged this frame, in screen pixels
+    pub camera_pan: Vec2,
+    pub cycle_camera_mode: bool,
+    // Hand the dogs over to the computer, or take them back
+    pub toggle_ai_handler: bool,
+    pub toggle_pause: bool,
+}
+
+// Tab to switch to the next dog, or 1 to 9 to pick one
+pub fn select_dog(input: ControlInput,
+                  mut selected: ResMut<SelectedDog>,
+                  dog_query: Query<&Dog>) {
+    let count = dog_query.iter().count();
+    if count == 0 {
+        return;
+    }
+
+    let next = if input.just_pressed(GameControl::NextDog) {
+        Some((selected.0 + 1) % count)
+    } else {
+        get_dog_selection(&input).filter(|number| *number < count)
+    };
+    if let Some(next) = next {
+        if let Some(dog) = dog_query.iter().find(|dog| dog.number() == next) {
+            info!("Working {}", dog.profile().name);
+        }
+        selected.0 = next;
+    }
 }
 
-// pub fn set_movement_actions(
-//     mut actions: ResMut<Actions>,
-//     keyboard_input: Res<Input<KeyCode>>,
-//     touch_input: Res<Touches>,
-//     player: Query<&Transform, With<Player>>,
-//     camera: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
-// ) {
-//     let mut player_movement = Vec2::new(
-//         get_movement(GameControl::Right, &keyboard_input)
-//             - get_movement(GameControl::Left, &keyboard_input),
-//         get_movement(GameControl::Up, &keyboard_input)
-//             - get_movement(GameControl::Down, &keyboard_input),
-//     );
-//
-//     if let Some(touch_position) = touch_input.first_pressed_position() {
-//         let (camera, camera_transform) = camera.single();
-//         if let Some(touch_position) = camera.viewport_to_world_2d(camera_transform, touch_position)
-//         {
-//             let diff = touch_position - player.single().translation.xy();
-//             if diff.length() > FOLLOW_EPSILON {
-//                 player_movement = diff.normalize();
-//             }
-//         }
-//     }
-//
-//     if player_movement != Vec2::ZERO {
-//         actions.player_movement = Some(player_movement.normalize());
-//     } else {
-//         actions.player_movement = None;
-//     }
-// }
-
-
-pub fn get_dog_command(mut actions: ResMut<Actions>,
-                       keyboard_input: Res<Input<KeyCode>>,
-                       mut dog_query: Query<&mut Dog>) {
-
-    if let Some(command) = get_command_for_input(&keyboard_input) {
-        for mut dog in dog_query.iter_mut() {
-            dog.set_command(&command);
+// The main keys command the selected dog, a dog with its own keys also answers to those
+pub fn get_dog_command(input: ControlInput,
+                       selected: Res<SelectedDog>,
+                       handler_post: Res<HandlerPost>,
+                       mut dog_query: Query<(&Transform, &mut Dog), Without<AiHandler>>) {
+    let main_command = get_command_for_input(&input, CommandKeys::Main);
+
+    for (transform, mut dog) in dog_query.iter_mut() {
+        let own_command = match dog.profile().keys {
+            CommandKeys::Main => None,
+            keys => get_command_for_input(&input, keys),
+        };
+        let command = own_command.or_else(|| main_command.filter(|_| dog.number() == selected.0));
+
+        if let Some(command) = command {
+            dog.call(&command, transform.translation.truncate(), handler_post.0);
         }
     }
-}
\ No newline at end of file
+}
+
+// I to let the computer handle the dogs, or to take them back
+pub fn set_handler_actions(mut actions: ResMut<Actions>, input: ControlInput) {
+    actions.toggle_ai_handler = input.just_pressed(GameControl::HandOver);
+}
+
+// P to pause the game or carry on
+pub fn set_pause_actions(mut actions: ResMut<Actions>, input: ControlInput) {
+    actions.toggle_pause = input.just_pressed(GameControl::Pause);
+}
+
+pub fn get_gate_command(input: ControlInput,
+                        mut gate_commands: EventWriter<GateCommand>) {
+    if input.just_pressed(GameControl::Gate) {
+        gate_commands.send(GateCommand::Toggle);
+    }
+}
+
+// Scroll or +/- to zoom, drag with the right mouse button to pan, C to switch camera mode
+pub fn set_camera_actions(mut actions: ResMut<Actions>,
+                          input: ControlInput,
+                          mut wheel_events: EventReader<MouseWheel>,
+                          mut motion_events: EventReader<MouseMotion>) {
+    let mut zoom = 0.;
+    for event in wheel_events.iter() {
+        zoom += match event.unit {
+            MouseScrollUnit::Line => event.y,
+            // Trackpads scroll in pixels, roughly a hundred to a notch
+            MouseScrollUnit::Pixel => event.y / 100.,
+        };
+    }
+    if input.just_pressed(GameControl::ZoomIn) {
+        zoom += 1.;
+    }
+    if input.just_pressed(GameControl::ZoomOut) {
+        zoom -= 1.;
+    }
+
+    let dragged: Vec2 = motion_events.iter().map(|event| event.delta).sum();
+
+    actions.camera_zoom = zoom;
+    actions.camera_pan = if input.pressed(GameControl::PanCamera) { dragged } else { Vec2::ZERO };
+    actions.cycle_camera_mode = input.just_pressed(GameControl::CameraMode);
+}
@@ -0,0 +1,63 @@
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::actions::game_control::GameControl;
+use crate::actions::input_map::ControlInput;
+use crate::ai_handler::AiHandler;
+use crate::camera::GameCamera;
+use crate::dog::{Dog, HandlerPost, SelectedDog};
+use crate::dog_target::DogTarget;
+use crate::sheep::Flock;
+
+// The pointer has to move this far (in screen pixels) with the button down before it counts as a drag
+const DRAG_THRESHOLD: f32 = 10.0;
+
+// Clicking on the field sends the selected dog round the flock to the point clicked, working as
+// far out from the flock as that point is. Keeping the button down and dragging moves where it stops
+// in or out, the side of the flock stays where it was clicked.
+#[allow(clippy::too_many_arguments)]
+pub fn get_mouse_target(
+    mut commands: Commands,
+    input: ControlInput,
+    flock: Res<Flock>,
+    selected: Res<SelectedDog>,
+    handler_post: Res<HandlerPost>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<GameCamera>>,
+    mut dog_query: Query<(Entity, &Transform, &mut Dog), Without<AiHandler>>,
+    // Where on screen the button went down, while it is held
+    mut pressed_at: Local<Option<Vec2>>,
+) {
+    if !input.pressed(GameControl::PointDog) {
+        *pressed_at = None;
+        return;
+    }
+
+    let Some(cursor) = windows.get_single().ok().and_then(|window| window.cursor_position()) else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = camera_query.get_single() else {
+        return;
+    };
+    let Some(point) = camera.viewport_to_world_2d(camera_transform, cursor) else {
+        return;
+    };
+    if flock.count == 0 {
+        return;
+    }
+    let Some((entity, transform, mut dog)) = dog_query.iter_mut().find(|(_, _, dog)| dog.number() == selected.0) else {
+        return;
+    };
+    // Pointing is only a way of telling the dog, one out of earshot takes no notice
+    if !dog.hears(transform.translation.truncate(), handler_post.0) {
+        return;
+    }
+
+    if input.just_pressed(GameControl::PointDog) {
+        *pressed_at = Some(cursor);
+        commands.entity(entity).insert(DogTarget::new(point - flock.centre));
+        dog.set_working_distance(point.distance(flock.centre));
+    } else if pressed_at.is_some_and(|pressed_at| pressed_at.distance(cursor) > DRAG_THRESHOLD) {
+        dog.set_working_distance(point.distance(flock.centre));
+    }
+}
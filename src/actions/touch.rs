@@ -0,0 +1,305 @@
+use bevy::input::touch::{TouchInput, TouchPhase};
+use bevy::prelude::*;
+
+use crate::ai_handler::AiHandler;
+use crate::camera::GameCamera;
+use crate::dog::{Dog, DogCommand, HandlerPost, SelectedDog};
+use crate::sheep::Flock;
+
+// Everything here is in screen pixels, down and right from the top left, and seconds
+
+// A finger that stays within this distance of where it went down hasn't moved
+const TAP_RADIUS: f32 = 20.0;
+// Longest a finger can stay down and still count as a tap
+const TAP_TIME: f32 = 0.25;
+// How long a finger has to stay put to count as holding
+const HOLD_TIME: f32 = 0.5;
+// Fingers of a two-finger tap have to come down within this long of each other
+const TWO_FINGER_GAP: f32 = 0.2;
+// Points closer together than this are skipped when working out how a stroke turns, so jitter doesn't add up
+const MIN_SEGMENT: f32 = 8.0;
+// A stroke turning through this much (in radians, about two thirds of the way round) is a circle
+const CIRCLE_TURN: f32 = 4.0;
+const MIN_CIRCLE_LENGTH: f32 = 100.0;
+// A swipe covers at least this much ground without turning more than this
+const MIN_SWIPE_LENGTH: f32 = 80.0;
+const MAX_SWIPE_TURN: f32 = 1.0;
+// How close to straight at the flock a swipe has to be for "Walk on" (about 35 degrees either side)
+const SWIPE_AT_FLOCK_COS: f32 = 0.8;
+
+// Where a finger was at a moment in time
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TouchSample {
+    time: f32,
+    position: Vec2,
+}
+
+// Everything one finger did between going down and coming back up
+#[derive(Debug, Clone, PartialEq)]
+struct TouchStroke {
+    id: u64,
+    samples: Vec<TouchSample>,
+    ended: bool,
+}
+
+impl TouchStroke {
+    fn start(&self) -> TouchSample {
+        self.samples[0]
+    }
+
+    fn end(&self) -> TouchSample {
+        self.samples[self.samples.len() - 1]
+    }
+
+    fn duration(&self) -> f32 {
+        self.end().time - self.start().time
+    }
+
+    // Furthest the finger got from where it went down
+    fn spread(&self) -> f32 {
+        let start = self.start().position;
+        self.samples
+            .iter()
+            .map(|sample| sample.position.distance(start))
+            .fold(0.0, f32::max)
+    }
+
+    fn length(&self) -> f32 {
+        self.samples
+            .windows(2)
+            .map(|pair| pair[0].position.distance(pair[1].position))
+            .sum()
+    }
+
+    // How far the stroke turns from start to end, positive for clockwise on screen
+    fn turning(&self) -> f32 {
+        let mut points: Vec<Vec2> = Vec::new();
+        for sample in &self.samples {
+            let far_enough = match points.last() {
+                Some(last) => last.distance(sample.position) >= MIN_SEGMENT,
+                None => true,
+            };
+            if far_enough {
+                points.push(sample.position);
+            }
+        }
+        let headings: Vec<Vec2> = points.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        // With y pointing down a positive angle turns clockwise
+        headings.windows(2).map(|pair| pair[0].angle_between(pair[1])).sum()
+    }
+
+    fn is_tap(&self) -> bool {
+        self.ended && self.duration() <= TAP_TIME && self.spread() <= TAP_RADIUS
+    }
+}
+
+// Turns touch input into dog commands:
+// - a circle drawn clockwise is "Come bye", anticlockwise is "Away"
+// - holding a finger still is "Lie down"
+// - a swipe straight at the flock is "Walk on"
+// - a quick tap with two fingers is "Easy"
+// It only sees what it is fed through `record`, so recorded sequences give the same result as live ones.
+#[derive(Resource, Debug, Default)]
+pub struct GestureRecogniser {
+    // Strokes since every finger was last lifted
+    strokes: Vec<TouchStroke>,
+    // Whether those strokes already gave a command, the rest of the gesture is then ignored
+    recognised: bool,
+}
+
+impl GestureRecogniser {
+    pub fn record(&mut self, id: u64, phase: TouchPhase, time: f32, position: Vec2) {
+        let sample = TouchSample { time, position };
+        match phase {
+            TouchPhase::Started => self.strokes.push(TouchStroke {
+                id,
+                samples: vec![sample],
+                ended: false,
+            }),
+            TouchPhase::Moved | TouchPhase::Ended | TouchPhase::Canceled => {
+                let Some(stroke) = self.strokes.iter_mut().find(|stroke| stroke.id == id && !stroke.ended) else {
+                    return;
+                };
+                stroke.samples.push(sample);
+                stroke.ended = phase != TouchPhase::Moved;
+            }
+        }
+    }
+
+    // The command, if any, from what has been recorded up to `now`. `flock` is where the flock
+    // is on screen, without it there's nothing to swipe at.
+    pub fn recognise(&mut self, now: f32, flock: Option<Vec2>) -> Option<DogCommand> {
+        if self.strokes.is_empty() {
+            return None;
+        }
+
+        let all_lifted = self.strokes.iter().all(|stroke| stroke.ended);
+        let command = if self.recognised {
+            None
+        } else if all_lifted {
+            self.recognise_lifted(flock)
+        } else {
+            self.recognise_held(now)
+        };
+
+        if command.is_some() {
+            self.recognised = true;
+        }
+        if all_lifted {
+            self.strokes.clear();
+            self.recognised = false;
+        }
+        command
+    }
+
+    // A lone finger held still long enough doesn't have to wait to be lifted
+    fn recognise_held(&self, now: f32) -> Option<DogCommand> {
+        match self.strokes.as_slice() {
+            [stroke] if now - stroke.start().time >= HOLD_TIME && stroke.spread() <= TAP_RADIUS => {
+                Some(DogCommand::LayDown)
+            }
+            _ => None,
+        }
+    }
+
+    fn recognise_lifted(&self, flock: Option<Vec2>) -> Option<DogCommand> {
+        match self.strokes.as_slice() {
+            [first, second] => {
+                let together = (first.start().time - second.start().time).abs() <= TWO_FINGER_GAP;
+                (together && first.is_tap() && second.is_tap()).then_some(DogCommand::Easy)
+            }
+            [stroke] => {
+                let turning = stroke.turning();
+                if turning.abs() >= CIRCLE_TURN && stroke.length() >= MIN_CIRCLE_LENGTH {
+                    return Some(if turning > 0. { DogCommand::ComeBye } else { DogCommand::Away });
+                }
+
+                let swipe = stroke.end().position - stroke.start().position;
+                let at_flock = flock.map(|flock| flock - stroke.start().position)?;
+                let straight = turning.abs() <= MAX_SWIPE_TURN && swipe.length() >= MIN_SWIPE_LENGTH;
+                let cos = swipe.normalize_or_zero().dot(at_flock.normalize_or_zero());
+                (straight && cos >= SWIPE_AT_FLOCK_COS).then_some(DogCommand::WalkOn)
+            }
+            _ => None,
+        }
+    }
+}
+
+// Gestures command the selected dog
+#[allow(clippy::too_many_arguments)]
+pub fn get_touch_command(
+    time: Res<Time>,
+    flock: Res<Flock>,
+    selected: Res<SelectedDog>,
+    handler_post: Res<HandlerPost>,
+    mut recogniser: ResMut<GestureRecogniser>,
+    mut touch_events: EventReader<TouchInput>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<GameCamera>>,
+    mut dog_query: Query<(&Transform, &mut Dog), Without<AiHandler>>,
+) {
+    let now = time.elapsed_seconds();
+    for event in touch_events.iter() {
+        recogniser.record(event.id, event.phase, now, event.position);
+    }
+
+    let flock_on_screen = camera_query
+        .get_single()
+        .ok()
+        .filter(|_| flock.count > 0)
+        .and_then(|(camera, camera_transform)| camera.world_to_viewport(camera_transform, flock.centre.extend(0.)));
+    let Some(command) = recogniser.recognise(now, flock_on_screen) else {
+        return;
+    };
+
+    if let Some((transform, mut dog)) = dog_query.iter_mut().find(|(_, dog)| dog.number() == selected.0) {
+        dog.call(&command, transform.translation.truncate(), handler_post.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Plays one finger's path through the recogniser, down at the first point and up at the last
+    fn stroke(recogniser: &mut GestureRecogniser, id: u64, path: &[(f32, Vec2)]) {
+        for (i, &(time, position)) in path.iter().enumerate() {
+            let phase = match i {
+                0 => TouchPhase::Started,
+                i if i == path.len() - 1 => TouchPhase::Ended,
+                _ => TouchPhase::Moved,
+            };
+            recogniser.record(id, phase, time, position);
+        }
+    }
+
+    // Once round a circle, clockwise on screen (y points down) or anticlockwise
+    fn circle(clockwise: bool) -> Vec<(f32, Vec2)> {
+        let direction = if clockwise { 1. } else { -1. };
+        (0..=32)
+            .map(|i| {
+                let angle = direction * i as f32 / 32. * std::f32::consts::TAU;
+                (i as f32 * 0.025, Vec2::new(400., 400.) + Vec2::from_angle(angle) * 80.)
+            })
+            .collect()
+    }
+
+    fn recognise(path: &[(f32, Vec2)], flock: Option<Vec2>) -> Option<DogCommand> {
+        let mut recogniser = GestureRecogniser::default();
+        stroke(&mut recogniser, 0, path);
+        recogniser.recognise(path[path.len() - 1].0, flock)
+    }
+
+    #[test]
+    fn clockwise_circle_is_come_bye() {
+        assert_eq!(recognise(&circle(true), None), Some(DogCommand::ComeBye));
+    }
+
+    #[test]
+    fn anticlockwise_circle_is_away() {
+        assert_eq!(recognise(&circle(false), None), Some(DogCommand::Away));
+    }
+
+    #[test]
+    fn hold_is_lie_down() {
+        let mut recogniser = GestureRecogniser::default();
+        recogniser.record(0, TouchPhase::Started, 0., Vec2::new(300., 300.));
+        recogniser.record(0, TouchPhase::Moved, 0.2, Vec2::new(303., 298.));
+        assert_eq!(recogniser.recognise(0.2, None), None);
+        assert_eq!(recogniser.recognise(0.6, None), Some(DogCommand::LayDown));
+
+        // Lifting the finger afterwards doesn't give another command
+        recogniser.record(0, TouchPhase::Ended, 0.9, Vec2::new(303., 298.));
+        assert_eq!(recogniser.recognise(0.9, None), None);
+    }
+
+    #[test]
+    fn swipe_at_the_flock_is_walk_on() {
+        let swipe: Vec<(f32, Vec2)> = (0..=10).map(|i| (i as f32 * 0.02, Vec2::new(300., 600. - i as f32 * 20.))).collect();
+        assert_eq!(recognise(&swipe, Some(Vec2::new(310., 100.))), Some(DogCommand::WalkOn));
+        // The same swipe with the flock off to the side, or unknown, is nothing
+        assert_eq!(recognise(&swipe, Some(Vec2::new(900., 600.))), None);
+        assert_eq!(recognise(&swipe, None), None);
+    }
+
+    #[test]
+    fn two_finger_tap_is_easy() {
+        let mut recogniser = GestureRecogniser::default();
+        recogniser.record(0, TouchPhase::Started, 0., Vec2::new(300., 300.));
+        recogniser.record(1, TouchPhase::Started, 0.05, Vec2::new(380., 310.));
+        assert_eq!(recogniser.recognise(0.05, None), None);
+        recogniser.record(0, TouchPhase::Ended, 0.12, Vec2::new(302., 301.));
+        recogniser.record(1, TouchPhase::Ended, 0.15, Vec2::new(381., 312.));
+        assert_eq!(recogniser.recognise(0.15, None), Some(DogCommand::Easy));
+    }
+
+    #[test]
+    fn short_wiggle_is_nothing() {
+        let wiggle: Vec<(f32, Vec2)> = (0..=8)
+            .map(|i| {
+                let side = if i % 2 == 0 { -12. } else { 12. };
+                (i as f32 * 0.04, Vec2::new(300. + side, 300. + i as f32 * 3.))
+            })
+            .collect();
+        assert_eq!(recognise(&wiggle, Some(Vec2::new(300., 100.))), None);
+    }
+}
@@ -0,0 +1,378 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ai_handler::AiHandler;
+use crate::dog::{Dog, DogCommand, HandlerPost, SelectedDog};
+
+// Pitch is tracked over frames this long, in seconds
+const FRAME_TIME: f32 = 0.02;
+// Frames quieter than this (RMS, with full scale at 1) are silence
+const SILENCE_LEVEL: f32 = 0.02;
+// The range a handler's whistle falls in, in Hz
+const MIN_PITCH: f32 = 500.0;
+const MAX_PITCH: f32 = 5000.0;
+// How closely a frame has to repeat itself at the pitch it is given to count as a tone rather than noise
+const MIN_CLARITY: f32 = 0.6;
+// The shortest lag that comes this close to the best one is taken as the period, longer lags are just its multiples
+const PERIOD_PEAK_RATIO: f32 = 0.9;
+// Gaps in a note up to this long are bridged, and notes shorter than this are dropped as clicks
+const MAX_NOTE_GAP: f32 = 0.04;
+const MIN_NOTE_LENGTH: f32 = 0.05;
+// A jump of more than this many semitones from one frame to the next starts a new note
+const NOTE_JUMP: f32 = 3.0;
+// A whistle is over once it has been quiet this long
+const END_SILENCE: f32 = 0.4;
+// How many semitones a handler can whistle above or below the templates and still be understood
+const MAX_TRANSPOSE: f32 = 7.0;
+// Semitones of pitch error that weigh the same as being out by a factor of e in length
+const LENGTH_WEIGHT: f32 = 4.0;
+// Worst match, in semitones of pitch error, that still counts as the command
+const MATCH_THRESHOLD: f32 = 2.5;
+// A handler's own whistles, next to wherever the game is started from
+#[cfg(not(target_arch = "wasm32"))]
+const WHISTLES_PATH: &str = "whistles.ron";
+
+// Some audio heard by the handler's microphone or read from a recording, mono, with full scale at 1.
+// Anything can send these, and whistles in them are turned into commands for the selected dog.
+#[derive(Event, Debug, Clone)]
+pub struct AudioInput {
+    pub samples: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+// One note of a whistle, gliding from `start` to `end` (in Hz) over `length` seconds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WhistleNote {
+    pub start: f32,
+    pub end: f32,
+    pub length: f32,
+}
+
+impl WhistleNote {
+    pub fn steady(pitch: f32, length: f32) -> Self {
+        Self { start: pitch, end: pitch, length }
+    }
+
+    pub fn glide(start: f32, end: f32, length: f32) -> Self {
+        Self { start, end, length }
+    }
+}
+
+// The notes a whistle is made up of, and the command it means
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WhistleTemplate {
+    pub command: DogCommand,
+    pub notes: Vec<WhistleNote>,
+}
+
+// The whistle for every command. The dogs know a standard set, unless `whistles.ron` teaches them
+// a handler's own (as a list of `(command: LayDown, notes: [(start: 2200, end: 2200, length: 0.8)])`)
+// or some are inserted before the game plugins. A whistle is matched by the shape of its notes,
+// so the same whistle a few semitones up or down is still understood.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WhistleTemplates(pub Vec<WhistleTemplate>);
+
+impl Default for WhistleTemplates {
+    fn default() -> Self {
+        use DogCommand::*;
+
+        let template = |command: DogCommand, notes: &[WhistleNote]| WhistleTemplate {
+            command,
+            notes: notes.to_vec(),
+        };
+        let steady = WhistleNote::steady;
+        let glide = WhistleNote::glide;
+
+        // Come bye rises and away falls, stops are long and flat, and the more notes the busier the command
+        Self(vec![
+            template(LayDown, &[steady(2200., 0.8)]),
+            template(ComeBye, &[steady(1800., 0.2), glide(1800., 2600., 0.4)]),
+            template(Away, &[steady(2600., 0.2), glide(2600., 1800., 0.4)]),
+            template(WalkOn, &[steady(2400., 0.1), steady(2400., 0.1), steady(2400., 0.1)]),
+            template(Easy, &[glide(2800., 1800., 1.0)]),
+            template(Steady, &[steady(2200., 0.4), steady(2200., 0.4)]),
+            template(ThatllDo, &[steady(2800., 0.12), steady(2500., 0.12), steady(2200., 0.12), steady(2000., 0.12)]),
+            template(LookBack, &[steady(3000., 0.1), steady(2000., 0.5)]),
+            template(GetOut, &[glide(1800., 3000., 0.5)]),
+            template(Here, &[steady(2000., 0.1), steady(2600., 0.1)]),
+        ])
+    }
+}
+
+impl WhistleTemplates {
+    pub fn load() -> Self {
+        Self::read().unwrap_or_default()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn read() -> Option<Self> {
+        let saved = std::fs::read_to_string(WHISTLES_PATH).ok()?;
+        match ron::from_str(&saved) {
+            Ok(templates) => {
+                info!("Listening for the whistles in {}", WHISTLES_PATH);
+                Some(templates)
+            }
+            Err(error) => {
+                warn!("Ignoring the whistles in {}: {}", WHISTLES_PATH, error);
+                None
+            }
+        }
+    }
+
+    // The web build has nowhere to keep them
+    #[cfg(target_arch = "wasm32")]
+    fn read() -> Option<Self> {
+        None
+    }
+
+    // The command whose whistle is closest to `notes`, if any is close enough
+    pub fn recognise(&self, notes: &[WhistleNote]) -> Option<DogCommand> {
+        self.0
+            .iter()
+            .filter_map(|template| match_notes(&template.notes, notes).map(|error| (template.command, error)))
+            .filter(|(_, error)| *error <= MATCH_THRESHOLD)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(command, _)| command)
+    }
+}
+
+fn semitones(from: f32, to: f32) -> f32 {
+    12. * (to / from).log2()
+}
+
+// How far apart two whistles are, once the heard one is shifted to the key of the template.
+// Whistles with a different number of notes can't be compared.
+fn match_notes(template: &[WhistleNote], heard: &[WhistleNote]) -> Option<f32> {
+    if template.is_empty() || template.len() != heard.len() {
+        return None;
+    }
+
+    let pitch_offsets: Vec<f32> = template
+        .iter()
+        .zip(heard)
+        .flat_map(|(template, heard)| [semitones(template.start, heard.start), semitones(template.end, heard.end)])
+        .collect();
+    let transpose = pitch_offsets.iter().sum::<f32>() / pitch_offsets.len() as f32;
+    if transpose.abs() > MAX_TRANSPOSE {
+        return None;
+    }
+
+    let pitch_error = pitch_offsets.iter().map(|offset| (offset - transpose).abs()).sum::<f32>() / pitch_offsets.len() as f32;
+    let length_error = template
+        .iter()
+        .zip(heard)
+        .map(|(template, heard)| (heard.length / template.length).ln().abs())
+        .sum::<f32>()
+        / template.len() as f32;
+    Some(pitch_error + LENGTH_WEIGHT * length_error)
+}
+
+// The pitch of one frame of audio, or None if it is too quiet or too noisy to have one.
+// Found from where the frame best lines up with a delayed copy of itself.
+fn frame_pitch(frame: &[f32], sample_rate: u32) -> Option<f32> {
+    let mean = frame.iter().sum::<f32>() / frame.len() as f32;
+    let frame: Vec<f32> = frame.iter().map(|sample| sample - mean).collect();
+    let level = (frame.iter().map(|sample| sample * sample).sum::<f32>() / frame.len() as f32).sqrt();
+    if level < SILENCE_LEVEL {
+        return None;
+    }
+
+    let rate = sample_rate as f32;
+    let min_lag = ((rate / MAX_PITCH).floor() as usize).max(1);
+    let max_lag = ((rate / MIN_PITCH).ceil() as usize).min(frame.len() / 2);
+    if min_lag + 2 > max_lag {
+        return None;
+    }
+
+    let correlation = |lag: usize| {
+        let (mut product, mut early, mut late) = (0., 0., 0.);
+        for i in 0..frame.len() - lag {
+            product += frame[i] * frame[i + lag];
+            early += frame[i] * frame[i];
+            late += frame[i + lag] * frame[i + lag];
+        }
+        if early * late > 0. { product / (early * late).sqrt() } else { 0. }
+    };
+    let correlations: Vec<f32> = (min_lag - 1..=max_lag + 1).map(correlation).collect();
+
+    // correlations[i] is for lag min_lag - 1 + i. Each peak has a parabola fitted through it and its
+    // neighbours, to find the period between whole samples and how high the peak really gets there.
+    let peaks: Vec<(f32, f32)> = (1..correlations.len() - 1)
+        .filter(|&i| correlations[i] >= correlations[i - 1] && correlations[i] >= correlations[i + 1])
+        .map(|i| {
+            let (before, at, after) = (correlations[i - 1], correlations[i], correlations[i + 1]);
+            let curve = before - 2. * at + after;
+            let shift = if curve < 0. { 0.5 * (before - after) / curve } else { 0. };
+            ((min_lag - 1 + i) as f32 + shift, at - 0.25 * (before - after) * shift)
+        })
+        .collect();
+    let best = peaks.iter().map(|(_, height)| *height).fold(f32::MIN, f32::max);
+    if best < MIN_CLARITY {
+        return None;
+    }
+
+    let (period, _) = peaks.into_iter().find(|(_, height)| *height >= best * PERIOD_PEAK_RATIO)?;
+    Some(rate / period)
+}
+
+// Splits a pitch contour, one pitch or silence per frame, into notes
+fn contour_notes(contour: &[Option<f32>]) -> Vec<WhistleNote> {
+    let max_gap = (MAX_NOTE_GAP / FRAME_TIME).round() as usize;
+    let mut runs: Vec<Vec<f32>> = Vec::new();
+    let mut gap = usize::MAX;
+
+    for pitch in contour {
+        let Some(pitch) = *pitch else {
+            gap = gap.saturating_add(1);
+            continue;
+        };
+        let continues = match runs.last().and_then(|run| run.last()) {
+            Some(last) => gap <= max_gap && semitones(*last, pitch).abs() <= NOTE_JUMP,
+            None => false,
+        };
+        if continues {
+            runs.last_mut().unwrap().push(pitch);
+        } else {
+            runs.push(vec![pitch]);
+        }
+        gap = 0;
+    }
+
+    let min_frames = (MIN_NOTE_LENGTH / FRAME_TIME).round() as usize;
+    runs.iter()
+        .filter(|run| run.len() >= min_frames)
+        .map(|run| {
+            // Averaged over a few frames at each end so one bad frame doesn't throw the glide off
+            let ends = run.len().min(3);
+            let start = run[..ends].iter().sum::<f32>() / ends as f32;
+            let end = run[run.len() - ends..].iter().sum::<f32>() / ends as f32;
+            WhistleNote::glide(start, end, run.len() as f32 * FRAME_TIME)
+        })
+        .collect()
+}
+
+// Listens to audio as it comes in, a frame at a time, and picks out whistles. A whistle is
+// matched once it is followed by enough silence, or when `finish` is called.
+#[derive(Resource, Debug, Default)]
+pub struct WhistleRecogniser {
+    sample_rate: u32,
+    // Samples waiting for a whole frame
+    pending: Vec<f32>,
+    // Pitch of each frame since the whistle started
+    contour: Vec<Option<f32>>,
+    // Frames of silence since the last note
+    quiet_frames: usize,
+}
+
+impl WhistleRecogniser {
+    // Commands for every whistle completed by `samples`
+    pub fn listen(&mut self, samples: &[f32], sample_rate: u32, templates: &WhistleTemplates) -> Vec<DogCommand> {
+        if sample_rate != self.sample_rate {
+            *self = Self {
+                sample_rate,
+                ..Default::default()
+            };
+        }
+
+        let frame_length = ((sample_rate as f32 * FRAME_TIME).round() as usize).max(1);
+        let end_frames = (END_SILENCE / FRAME_TIME).round() as usize;
+        let mut commands = Vec::new();
+
+        self.pending.extend_from_slice(samples);
+        let mut used = 0;
+        while self.pending.len() - used >= frame_length {
+            let pitch = frame_pitch(&self.pending[used..used + frame_length], sample_rate);
+            used += frame_length;
+
+            match pitch {
+                Some(_) => self.quiet_frames = 0,
+                None if self.contour.is_empty() => continue,
+                None => self.quiet_frames += 1,
+            }
+            self.contour.push(pitch);
+            if self.quiet_frames >= end_frames {
+                commands.extend(self.finish(templates));
+            }
+        }
+        self.pending.drain(..used);
+        commands
+    }
+
+    // Matches whatever has been heard so far as a whole whistle, for the end of a recording
+    pub fn finish(&mut self, templates: &WhistleTemplates) -> Option<DogCommand> {
+        let notes = contour_notes(&self.contour);
+        self.contour.clear();
+        self.quiet_frames = 0;
+        if notes.is_empty() {
+            return None;
+        }
+
+        let command = templates.recognise(&notes);
+        match command {
+            Some(command) => debug!("Heard the whistle for {}", command.name()),
+            None => debug!("Heard a whistle that isn't a command: {:?}", notes),
+        }
+        command
+    }
+}
+
+// Every command whistled in a recording, in order
+pub fn recognise_recording(samples: &[f32], sample_rate: u32, templates: &WhistleTemplates) -> Vec<DogCommand> {
+    let mut recogniser = WhistleRecogniser::default();
+    let mut commands = recogniser.listen(samples, sample_rate, templates);
+    commands.extend(recogniser.finish(templates));
+    commands
+}
+
+// Reads a WAV file as mono samples, with full scale at 1, and its sample rate
+pub fn read_wav(path: impl AsRef<Path>) -> Result<(Vec<f32>, u32), hound::Error> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let full_scale = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 / full_scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    let channels = spec.channels.max(1) as usize;
+    let samples = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+    Ok((samples, spec.sample_rate))
+}
+
+// Every command whistled in a WAV file, in order
+pub fn recognise_wav(path: impl AsRef<Path>, templates: &WhistleTemplates) -> Result<Vec<DogCommand>, hound::Error> {
+    let (samples, sample_rate) = read_wav(path)?;
+    Ok(recognise_recording(&samples, sample_rate, templates))
+}
+
+// Whistles command the selected dog, the same as the main keys
+pub fn get_whistle_command(
+    templates: Res<WhistleTemplates>,
+    selected: Res<SelectedDog>,
+    handler_post: Res<HandlerPost>,
+    mut recogniser: ResMut<WhistleRecogniser>,
+    mut audio_events: EventReader<AudioInput>,
+    mut dog_query: Query<(&Transform, &mut Dog), Without<AiHandler>>,
+) {
+    let mut commands = Vec::new();
+    for audio in audio_events.iter() {
+        commands.extend(recogniser.listen(&audio.samples, audio.sample_rate, &templates));
+    }
+
+    let Some((transform, mut dog)) = dog_query.iter_mut().find(|(_, dog)| dog.number() == selected.0) else {
+        return;
+    };
+    for command in commands {
+        dog.call(&command, transform.translation.truncate(), handler_post.0);
+    }
+}
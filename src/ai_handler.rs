@@ -0,0 +1,192 @@
+use bevy::prelude::*;
+
+use crate::actions::Actions;
+use crate::dog::DogCommand::{Away, ComeBye, GetOut, LayDown, LookBack, ThatllDo, WalkOn};
+use crate::dog::{flank_towards, move_dog, Dog, DogCommand, HandlerPost};
+use crate::pen::{Gate, GateCommand, Pen, PenProgress};
+use crate::sheep::Flock;
+use crate::simulation::SimulationSet;
+use crate::GameState;
+
+// The flock counts as gathered while its radius is within this much per square root of its size
+const GATHERED_SPACING: f32 = 45.0;
+// Once the dog goes to gather the flock, it keeps at it until the flock is this much tighter than gathered
+const REGATHER_RATIO: f32 = 0.8;
+// How far round the flock (in radians) the dog can get from the driving position before it flanks
+// back, and how close it has to get again before it stops flanking
+const START_FLANK_ANGLE: f32 = 0.6;
+const STOP_FLANK_ANGLE: f32 = 0.2;
+// While driving, the dog walks on until it is this far outside the flock, close enough to keep it moving
+const DRIVE_CLEARANCE: f32 = 100.0;
+// Once the middle of the flock is this close to the target the dog lies down and holds it there
+const HOLD_DISTANCE: f32 = 60.0;
+// Room a flanking dog leaves between itself and the edge of the flock
+const FLANK_CLEARANCE: f32 = 100.0;
+// Shortest time between two commands, a handler can only whistle so fast
+const COMMAND_GAP: f32 = 0.3;
+
+pub struct AiHandlerPlugin;
+
+/// This plugin lets the computer handle the dogs. It reads the flock and gives the same
+/// [`DogCommand`]s a player would, using Strömbom's collect and drive heuristic: "Look back" to
+/// gather the sheep whenever they spread out, otherwise flank round behind them and walk on to
+/// drive them to the pen, then shut the gate and call the dog off.
+impl Plugin for AiHandlerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Handler>()
+            .add_systems(
+                Update,
+                (toggle_handler, assign_handler)
+                    .chain()
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(
+                FixedUpdate,
+                handle_dogs
+                    .before(move_dog)
+                    .in_set(SimulationSet::Step)
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+// Who gives the dogs their commands. Insert one before the game plugins to pick it,
+// the player can also hand over to the computer and take back control while playing.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Handler {
+    #[default]
+    Player,
+    Ai,
+}
+
+// Marks a dog the computer is handling, with what it has decided so far
+#[derive(Component, Debug, Default)]
+pub struct AiHandler {
+    collecting: bool,
+    flanking: bool,
+    since_command: f32,
+}
+
+impl AiHandler {
+    // The command the dog should be working to right now, in the spirit of Strömbom et al. (2014)
+    fn decide(&mut self, position: Vec2, dog: &Dog, flock: &Flock, target: Vec2, all_penned: bool) -> DogCommand {
+        if flock.count == 0 || all_penned {
+            return ThatllDo;
+        }
+
+        let gathered = GATHERED_SPACING * (flock.count as f32).sqrt();
+        self.collecting = if self.collecting {
+            flock.radius > gathered * REGATHER_RATIO
+        } else {
+            flock.radius > gathered
+        };
+        if self.collecting {
+            return LookBack;
+        }
+
+        if flock.centre.distance(target) < HOLD_DISTANCE {
+            return LayDown;
+        }
+
+        // Driving: get round to the far side of the flock from the target, then walk on to it
+        let behind = (flock.centre - target).normalize_or_zero();
+        let offset = position - flock.centre;
+        let error = if offset == Vec2::ZERO { 0.0 } else { offset.angle_between(behind) };
+        self.flanking = if self.flanking {
+            error.abs() > STOP_FLANK_ANGLE
+        } else {
+            error.abs() > START_FLANK_ANGLE
+        };
+
+        if self.flanking {
+            let flanking = matches!(dog.command(), ComeBye | Away);
+            if flanking && dog.working_distance() < flock.radius + FLANK_CLEARANCE {
+                return GetOut;
+            }
+            // Keeps flanking until it is back behind the flock, however close it gets
+            return flank_towards(offset, behind, 0.0);
+        }
+
+        if offset.length() > flock.radius + DRIVE_CLEARANCE {
+            WalkOn
+        } else {
+            LayDown
+        }
+    }
+}
+
+fn toggle_handler(actions: Res<Actions>, mut handler: ResMut<Handler>) {
+    if actions.toggle_ai_handler {
+        *handler = match *handler {
+            Handler::Player => Handler::Ai,
+            Handler::Ai => Handler::Player,
+        };
+        info!("Dogs handled by: {:?}", *handler);
+    }
+}
+
+// Keeps every dog, including any spawned since, handled by whoever `Handler` says
+fn assign_handler(
+    mut commands: Commands,
+    handler: Res<Handler>,
+    dog_query: Query<(Entity, Option<&AiHandler>), With<Dog>>,
+) {
+    for (entity, ai_handler) in &dog_query {
+        match (*handler, ai_handler) {
+            (Handler::Ai, None) => {
+                commands.entity(entity).insert(AiHandler::default());
+            }
+            (Handler::Player, Some(_)) => {
+                commands.entity(entity).remove::<AiHandler>();
+            }
+            _ => {}
+        }
+    }
+}
+
+// Drives the flock to the middle of the first pen, or without one fetches it to the handler
+fn herding_target(pen_query: &Query<&Pen>, handler_post: &HandlerPost) -> Vec2 {
+    pen_query
+        .iter()
+        .next()
+        .map(|pen| pen.polygon.iter().copied().sum::<Vec2>() / pen.polygon.len().max(1) as f32)
+        .unwrap_or(handler_post.0)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn handle_dogs(
+    fixed_time: Res<FixedTime>,
+    flock: Res<Flock>,
+    progress: Res<PenProgress>,
+    handler_post: Res<HandlerPost>,
+    pen_query: Query<&Pen>,
+    gate_query: Query<&Gate>,
+    mut gate_commands: EventWriter<GateCommand>,
+    mut dog_query: Query<(&Transform, &mut Dog, &mut AiHandler)>,
+) {
+    if dog_query.is_empty() {
+        return;
+    }
+
+    let target = herding_target(&pen_query, &handler_post);
+    let all_penned = flock.count > 0 && progress.penned_count() == flock.count;
+
+    for (transform, mut dog, mut handler) in &mut dog_query {
+        handler.since_command += fixed_time.period.as_secs_f32();
+        if handler.since_command < COMMAND_GAP {
+            continue;
+        }
+
+        let command = handler.decide(transform.translation.truncate(), &dog, &flock, target, all_penned);
+        // Each "Get out" widens the flank a step further, so it's worth repeating
+        if command == GetOut || *dog.command() != command {
+            debug!("AI handler: {:?}", command);
+            dog.set_command(&command);
+            handler.since_command = 0.0;
+        }
+    }
+
+    if all_penned && gate_query.iter().any(|gate| gate.open) {
+        gate_commands.send(GateCommand::Close);
+    }
+}
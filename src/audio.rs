@@ -11,6 +11,7 @@ impl Plugin for InternalAudioPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(AudioPlugin)
             .add_systems(OnEnter(GameState::Playing), start_audio)
+            .add_systems(OnExit(GameState::Playing), stop_audio)
             .add_systems(
                 Update,
                 control_flying_sound
@@ -32,6 +33,18 @@ fn start_audio(mut commands: Commands, audio_assets: Res<AudioAssets>, audio: Re
     commands.insert_resource(FlyingAudio(handle));
 }
 
+// Each run starts its own loop, so the last one is stopped once the run is over
+fn stop_audio(
+    mut commands: Commands,
+    audio: Res<FlyingAudio>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+) {
+    if let Some(instance) = audio_instances.get_mut(&audio.0) {
+        instance.stop(AudioTween::default());
+    }
+    commands.remove_resource::<FlyingAudio>();
+}
+
 fn control_flying_sound(
     actions: Res<Actions>,
     audio: Res<FlyingAudio>,
@@ -39,15 +52,11 @@ fn control_flying_sound(
 ) {
     if let Some(instance) = audio_instances.get_mut(&audio.0) {
         match instance.state() {
-            PlaybackState::Paused { .. } => {
-                if actions.player_movement.is_some() {
-                    instance.resume(AudioTween::default());
-                }
+            PlaybackState::Paused { .. } if actions.player_movement.is_some() => {
+                instance.resume(AudioTween::default());
             }
-            PlaybackState::Playing { .. } => {
-                if actions.player_movement.is_none() {
-                    instance.pause(AudioTween::default());
-                }
+            PlaybackState::Playing { .. } if actions.player_movement.is_none() => {
+                instance.pause(AudioTween::default());
             }
             _ => {}
         }
@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+use crate::actions::Actions;
+use crate::dog::{Dog, SelectedDog};
+use crate::field::Field;
+use crate::sheep::Flock;
+use crate::GameState;
+
+// Each zoom step scales the view by this much
+const ZOOM_STEP: f32 = 1.1;
+// Limits on the player's zoom, relative to what the mode frames
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 4.0;
+// Space left around the flock when fitting it in view
+const FIT_FLOCK_MARGIN: f32 = 150.0;
+// How quickly the camera catches up with what it is framing, higher is snappier
+const CAMERA_SMOOTHING: f32 = 5.0;
+
+pub struct CameraPlugin;
+
+/// This plugin owns the one camera. While playing it frames the selected dog, the flock or the whole field
+/// depending on its [`CameraMode`], and the player can zoom and pan on top of that.
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, spawn_camera).add_systems(
+            Update,
+            (control_camera, move_camera)
+                .chain()
+                .run_if(in_state(GameState::Playing)),
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CameraMode {
+    FollowDog,
+    FitFlock,
+    #[default]
+    WholeField,
+}
+
+impl CameraMode {
+    fn next(self) -> Self {
+        match self {
+            CameraMode::FollowDog => CameraMode::FitFlock,
+            CameraMode::FitFlock => CameraMode::WholeField,
+            CameraMode::WholeField => CameraMode::FollowDog,
+        }
+    }
+}
+
+#[derive(Component, Debug, Default)]
+pub struct GameCamera {
+    pub mode: CameraMode,
+    // Multiplies the scale the mode picks, below 1 is zoomed in
+    pub zoom: f32,
+    // World space offset from whatever the mode is centred on
+    pub pan: Vec2,
+}
+
+fn spawn_camera(mut commands: Commands) {
+    commands.spawn(Camera2dBundle::default()).insert(GameCamera {
+        zoom: 1.0,
+        ..Default::default()
+    });
+}
+
+fn control_camera(actions: Res<Actions>, mut camera_query: Query<(&mut GameCamera, &OrthographicProjection)>) {
+    for (mut camera, projection) in &mut camera_query {
+        if actions.cycle_camera_mode {
+            camera.mode = camera.mode.next();
+            camera.zoom = 1.0;
+            camera.pan = Vec2::ZERO;
+            info!("Camera mode: {:?}", camera.mode);
+        }
+
+        camera.zoom = (camera.zoom / ZOOM_STEP.powf(actions.camera_zoom)).clamp(MIN_ZOOM, MAX_ZOOM);
+        // Dragging pulls the field along with the mouse, and screen y points down
+        camera.pan += Vec2::new(-actions.camera_pan.x, actions.camera_pan.y) * projection.scale;
+    }
+}
+
+fn move_camera(
+    time: Res<Time>,
+    field: Res<Field>,
+    flock: Res<Flock>,
+    selected: Res<SelectedDog>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    dog_query: Query<(&Transform, &Dog), Without<GameCamera>>,
+    mut camera_query: Query<(&GameCamera, &mut Transform, &mut OrthographicProjection)>,
+) {
+    let Ok(window) = windows.get_single() else {
+        return;
+    };
+    let view = Vec2::new(window.width(), window.height());
+
+    for (camera, mut transform, mut projection) in &mut camera_query {
+        // Where the mode wants the camera, and how many world units each pixel should cover
+        let (centre, scale) = match camera.mode {
+            CameraMode::FollowDog => {
+                let dog = dog_query
+                    .iter()
+                    .find(|(_, dog)| dog.number() == selected.0)
+                    .map(|(transform, _)| transform.translation.truncate())
+                    .unwrap_or_default();
+                (dog, 1.0)
+            }
+            CameraMode::FitFlock => {
+                let size = 2.0 * (flock.radius + FIT_FLOCK_MARGIN);
+                (flock.centre, size / view.min_element())
+            }
+            CameraMode::WholeField => (Vec2::ZERO, (Vec2::new(field.width, field.height) / view).max_element()),
+        };
+
+        let blend = 1.0 - (-CAMERA_SMOOTHING * time.delta_seconds()).exp();
+        let target = (centre + camera.pan).extend(transform.translation.z);
+        transform.translation = transform.translation.lerp(target, blend);
+        projection.scale += (scale * camera.zoom - projection.scale) * blend;
+    }
+}
@@ -0,0 +1,210 @@
+use bevy::prelude::*;
+
+use crate::actions::{Binding, GameControl, InputMap};
+use crate::loading::FontAssets;
+use crate::GameState;
+
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const WAITING_COLOR: Color = Color::rgb(1.0, 0.85, 0.4);
+const BUTTON_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const HOVERED_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+
+pub struct ControlsPlugin;
+
+/// This plugin shows every control with what it is bound to during the State `GameState::Controls`.
+/// Clicking a control and then pressing a key, mouse button or gamepad button binds it, Escape
+/// or a click cancels. Every change is saved straight away, and the screen is removed when that state is exited.
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<AwaitingBinding>()
+            .add_systems(OnEnter(GameState::Controls), setup_controls)
+            .add_systems(
+                Update,
+                // Capturing first, so the click that picks a control isn't bound to it
+                (capture_binding, click_controls_buttons, update_binding_labels)
+                    .chain()
+                    .run_if(in_state(GameState::Controls)),
+            )
+            .add_systems(OnExit(GameState::Controls), cleanup_controls);
+    }
+}
+
+// The control the next key or button press goes to
+#[derive(Resource, Default)]
+struct AwaitingBinding(Option<GameControl>);
+
+#[derive(Component)]
+struct ControlsScreen;
+
+#[derive(Component)]
+enum ControlsButton {
+    Rebind(GameControl),
+    Reset,
+    Back,
+}
+
+fn setup_controls(mut commands: Commands, font_assets: Res<FontAssets>) {
+    let style = |font_size: f32| TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size,
+        color: TEXT_COLOR,
+    };
+    let button = |width: f32, height: f32| ButtonBundle {
+        style: Style {
+            width: Val::Px(width),
+            height: Val::Px(height),
+            margin: UiRect::all(Val::Px(2.0)),
+            padding: UiRect::horizontal(Val::Px(8.0)),
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        background_color: BUTTON_COLOR.into(),
+        ..Default::default()
+    };
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                padding: UiRect::all(Val::Px(20.0)),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(ControlsScreen)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section("Controls", style(40.0)));
+
+            // Too many controls for one column, so they wrap into as many as it takes
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_grow: 1.0,
+                        flex_direction: FlexDirection::Column,
+                        flex_wrap: FlexWrap::Wrap,
+                        align_content: AlignContent::Center,
+                        margin: UiRect::vertical(Val::Px(10.0)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for control in GameControl::all() {
+                        parent
+                            .spawn(button(360.0, 26.0))
+                            .insert(ControlsButton::Rebind(control))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section("", style(18.0)));
+                            });
+                    }
+                });
+
+            parent
+                .spawn(NodeBundle::default())
+                .with_children(|parent| {
+                    for (label, action) in [("Reset", ControlsButton::Reset), ("Back", ControlsButton::Back)] {
+                        parent
+                            .spawn(button(120.0, 50.0))
+                            .insert(action)
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(label, style(32.0)));
+                            });
+                    }
+                });
+        });
+}
+
+fn capture_binding(
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut awaiting: ResMut<AwaitingBinding>,
+    mut input_map: ResMut<InputMap>,
+) {
+    let Some(control) = awaiting.0 else {
+        return;
+    };
+
+    // The left button is kept for clicking around the screen, so it cancels like Escape
+    if keys.just_pressed(KeyCode::Escape) || mouse_buttons.just_pressed(MouseButton::Left) {
+        awaiting.0 = None;
+    } else if let Some(binding) = Binding::just_pressed(&keys, &mouse_buttons, &gamepad_buttons) {
+        info!("{} bound to {}", control.name(), binding.name());
+        input_map.bind(control, binding);
+        input_map.save();
+        awaiting.0 = None;
+    }
+}
+
+fn click_controls_buttons(
+    mut state: ResMut<NextState<GameState>>,
+    mut awaiting: ResMut<AwaitingBinding>,
+    mut input_map: ResMut<InputMap>,
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &ControlsButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+) {
+    for (interaction, mut color, button) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => match button {
+                ControlsButton::Rebind(control) => awaiting.0 = Some(*control),
+                ControlsButton::Reset => {
+                    *input_map = InputMap::default();
+                    input_map.save();
+                    awaiting.0 = None;
+                }
+                ControlsButton::Back => state.set(GameState::Menu),
+            },
+            Interaction::Hovered => {
+                *color = HOVERED_COLOR.into();
+            }
+            Interaction::None => {
+                *color = BUTTON_COLOR.into();
+            }
+        }
+    }
+}
+
+fn update_binding_labels(
+    awaiting: Res<AwaitingBinding>,
+    input_map: Res<InputMap>,
+    button_query: Query<(&ControlsButton, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    if !awaiting.is_changed() && !input_map.is_changed() {
+        return;
+    }
+
+    for (button, children) in &button_query {
+        let ControlsButton::Rebind(control) = button else {
+            continue;
+        };
+        let Some(mut text) = children.first().and_then(|child| text_query.get_mut(*child).ok()) else {
+            continue;
+        };
+
+        let section = &mut text.sections[0];
+        if awaiting.0 == Some(*control) {
+            section.value = format!("{}: press a key or button", control.name());
+            section.style.color = WAITING_COLOR;
+        } else {
+            let bindings: Vec<String> = input_map.bindings(*control).iter().map(Binding::name).collect();
+            let bound = if bindings.is_empty() { "-".to_string() } else { bindings.join(", ") };
+            section.value = format!("{}: {}", control.name(), bound);
+            section.style.color = TEXT_COLOR;
+        }
+    }
+}
+
+fn cleanup_controls(
+    mut commands: Commands,
+    mut awaiting: ResMut<AwaitingBinding>,
+    screen: Query<Entity, With<ControlsScreen>>,
+) {
+    awaiting.0 = None;
+    commands.entity(screen.single()).despawn_recursive();
+}
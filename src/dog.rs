@@ -1,100 +1,475 @@
 use bevy::prelude::*;
-use crate::actions::Actions;
-use crate::dog::DogCommand::{Away, ComeBye, LayDown};
+use serde::{Deserialize, Serialize};
+use crate::actions::CommandKeys;
+use crate::dog::DogCommand::{Away, ComeBye, Easy, GetOut, Here, LayDown, LookBack, Steady, ThatllDo, WalkOn};
 
-use crate::GameState;
+use crate::{GameState, RunEntity};
+use crate::field::Field;
+use crate::level::CurrentLevel;
 use crate::loading::TextureAssets;
+use crate::obstacle::{resolve_movement, Obstacle};
+use crate::sheep::Flock;
+use crate::simulation::{Interpolated, SimulationSet};
+
+// Full running speed in world units per second
+const DOG_SPEED: f32 = 300.0;
+// How close the dog's middle can get to an obstacle
+const DOG_RADIUS: f32 = 20.0;
+// Fraction of full speed the dog slows to on "Easy" and "Steady"
+const EASY_PACE: f32 = 0.35;
+const STEADY_PACE: f32 = 0.6;
+// How far from the flock centre the dog flanks by default
+const DEFAULT_WORKING_DISTANCE: f32 = 250.0;
+// How much each "Get out" widens the working distance, and how wide it can get
+const GET_OUT_STEP: f32 = 75.0;
+const MAX_WORKING_DISTANCE: f32 = 600.0;
+// Closest in a dog can be set to work, any tighter and it would be in among the sheep
+const MIN_WORKING_DISTANCE: f32 = 100.0;
+// How close the dog needs to get to a point it is running to before it stops
+const ARRIVE_DISTANCE: f32 = 10.0;
+// On "Walk on" the dog stops pushing once it is this close to the flock centre
+const WALK_ON_STOP_DISTANCE: f32 = 40.0;
+// How hard a flanking dog corrects back onto its working distance
+const FLANK_CORRECTION: f32 = 2.0;
+// A dog worked by voice can't hear the handler any further off than this, a whistle carries across the field
+const VOICE_RANGE: f32 = 500.0;
+// Space between dogs starting side by side
+const DOG_SPACING: f32 = 60.0;
 
 pub struct DogPlugin;
 
 //enum of sheepdog commands
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum DogCommand {
+    // Flank clockwise around the flock
     ComeBye,
+    // Flank anticlockwise around the flock
     Away,
+    // Stop where you are
     LayDown,
+    // Walk straight in towards the flock
     WalkOn,
+    // Slow right down, keep doing what you're doing
     Easy,
+    // Ease off to a steady working pace, keep doing what you're doing
+    Steady,
+    // "That'll do", stop working and come back to the handler
+    ThatllDo,
+    // Turn away from the flock and go out for the sheep that got left behind
+    LookBack,
+    // Widen the flank, moving straight out if the dog isn't flanking
+    GetOut,
+    // Come round to the handler's side of the flock
+    Here,
+}
+
+impl DogCommand {
+    // Every command, in the order a held key takes precedence
+    pub const ALL: [DogCommand; 10] = [LayDown, Away, ComeBye, WalkOn, Easy, Steady, ThatllDo, LookBack, GetOut, Here];
+
+    // What the handler calls out for the command
+    pub fn name(&self) -> &'static str {
+        match self {
+            ComeBye => "Come bye",
+            Away => "Away to me",
+            LayDown => "Lie down",
+            WalkOn => "Walk on",
+            Easy => "Easy",
+            Steady => "Steady",
+            ThatllDo => "That'll do",
+            LookBack => "Look back",
+            GetOut => "Get out",
+            Here => "Here",
+        }
+    }
+}
+
+// The flank that takes a dog at `bearing` from the flock centre the short way round to `wanted`,
+// or "Lie down" once it is within `arrive_angle` (in radians) of it
+pub fn flank_towards(bearing: Vec2, wanted: Vec2, arrive_angle: f32) -> DogCommand {
+    let error = if bearing == Vec2::ZERO { 0. } else { bearing.angle_between(wanted) };
+    // Away goes anticlockwise, the way a positive angle turns
+    if error.abs() < arrive_angle {
+        LayDown
+    } else if error > 0. {
+        Away
+    } else {
+        ComeBye
+    }
+}
+
+// How a dog has been trained to take its commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CallStyle {
+    #[default]
+    Whistle,
+    Voice,
+}
+
+impl CallStyle {
+    pub fn name(self) -> &'static str {
+        match self {
+            CallStyle::Whistle => "whistle",
+            CallStyle::Voice => "voice",
+        }
+    }
+
+    // How far from the handler a call still reaches the dog
+    pub fn range(self) -> f32 {
+        match self {
+            CallStyle::Whistle => f32::INFINITY,
+            CallStyle::Voice => VOICE_RANGE,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DogProfile {
+    pub name: String,
+    pub calls: CallStyle,
+    pub keys: CommandKeys,
+}
+
+impl DogProfile {
+    pub fn new(name: &str, calls: CallStyle, keys: CommandKeys) -> Self {
+        Self {
+            name: name.to_string(),
+            calls,
+            keys,
+        }
+    }
+}
+
+// Something a dog was told, by a command or by setting its pace or working distance directly.
+// A dog's orders are all that decide how it works, so giving them again plays the dog back exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DogOrder {
+    Command(DogCommand),
+    Pace(f32),
+    WorkingDistance(f32),
 }
 
+// The dogs being worked, numbered in order from 1. Insert one before the game plugins to pick them,
+// by default there is a single dog on the main keys.
+#[derive(Resource, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Kennel(pub Vec<DogProfile>);
+
+impl Default for Kennel {
+    fn default() -> Self {
+        Self(vec![DogProfile::new("Moss", CallStyle::Whistle, CommandKeys::Main)])
+    }
+}
+
+impl Kennel {
+    // Two dogs worked together, as in a brace trial: one on the whistle and main keys,
+    // the other on voice commands and the number pad
+    pub fn brace() -> Self {
+        Self(vec![
+            DogProfile::new("Moss", CallStyle::Whistle, CommandKeys::Main),
+            DogProfile::new("Fly", CallStyle::Voice, CommandKeys::Numpad),
+        ])
+    }
+}
+
+// The dog the main keys command and the camera follows, as an index into the kennel
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SelectedDog(pub usize);
+
+// Where the handler stands; the dog starts here and comes back here on "That'll do"
+#[derive(Resource)]
+pub struct HandlerPost(pub Vec2);
+
+impl Default for HandlerPost {
+    fn default() -> Self {
+        Self(Vec2::new(0., -600.))
+    }
+}
 
 #[derive(Component)]
 pub struct Dog {
-    command: DogCommand
+    // Its place in the kennel
+    number: usize,
+    profile: DogProfile,
+    // What the dog is doing. "Easy" and "Steady" only slow it down at whatever that is, so they
+    // are never kept here.
+    command: DogCommand,
+    // Fraction of full speed, lowered by "Easy" or "Steady" and restored by any other command
+    pace: f32,
+    working_distance: f32,
+    // Every order that changed the dog since they were last collected
+    orders: Vec<DogOrder>,
 }
 
 impl Dog {
-    pub fn new() -> Self {
-        Self { command: Away }
+    pub fn new(number: usize, profile: DogProfile) -> Self {
+        Self {
+            number,
+            profile,
+            command: Away,
+            pace: 1.0,
+            working_distance: DEFAULT_WORKING_DISTANCE,
+            orders: Vec::new(),
+        }
+    }
+    pub fn number(&self) -> usize {
+        self.number
     }
+
+    pub fn profile(&self) -> &DogProfile {
+        &self.profile
+    }
+
+    pub fn command(&self) -> &DogCommand {
+        &self.command
+    }
+
+    pub fn working_distance(&self) -> f32 {
+        self.working_distance
+    }
+
+    pub fn set_working_distance(&mut self, working_distance: f32) {
+        let working_distance = working_distance.clamp(MIN_WORKING_DISTANCE, MAX_WORKING_DISTANCE);
+        if working_distance != self.working_distance {
+            self.working_distance = working_distance;
+            self.orders.push(DogOrder::WorkingDistance(working_distance));
+        }
+    }
+
+    pub fn pace(&self) -> f32 {
+        self.pace
+    }
+
+    // Sets the fraction of full speed directly, for handlers who can whistle softer or harder
+    pub fn set_pace(&mut self, pace: f32) {
+        let pace = pace.clamp(0.0, 1.0);
+        if pace != self.pace {
+            self.pace = pace;
+            self.orders.push(DogOrder::Pace(pace));
+        }
+    }
+
     pub fn set_command(&mut self, command: &DogCommand) {
-        self.command = command.clone();;
+        let before = (self.command, self.pace, self.working_distance);
+        match command {
+            Easy => {
+                self.pace = EASY_PACE;
+            }
+            Steady => {
+                self.pace = STEADY_PACE;
+            }
+            GetOut => {
+                self.working_distance = (self.working_distance + GET_OUT_STEP).min(MAX_WORKING_DISTANCE);
+                // A flanking dog keeps flanking, just wider
+                if !matches!(self.command, ComeBye | Away) {
+                    self.command = GetOut;
+                }
+            }
+            ThatllDo => {
+                // Work is over, the next outrun starts from the usual distance
+                self.command = ThatllDo;
+                self.pace = 1.0;
+                self.working_distance = DEFAULT_WORKING_DISTANCE;
+            }
+            _ => {
+                self.command = *command;
+                self.pace = 1.0;
+            }
+        }
+        // An order that changed nothing needn't be played back, so a held key or whistle is only kept once
+        if (self.command, self.pace, self.working_distance) != before {
+            self.orders.push(DogOrder::Command(*command));
+        }
     }
 
+    pub fn obey(&mut self, order: DogOrder) {
+        match order {
+            DogOrder::Command(command) => self.set_command(&command),
+            DogOrder::Pace(pace) => self.set_pace(pace),
+            DogOrder::WorkingDistance(working_distance) => self.set_working_distance(working_distance),
+        }
+    }
 
+    // The orders taken since this was last called, oldest first
+    pub fn take_orders(&mut self) -> Vec<DogOrder> {
+        std::mem::take(&mut self.orders)
+    }
+
+    // Whether a call from the handler's post reaches the dog
+    pub fn hears(&self, position: Vec2, handler_post: Vec2) -> bool {
+        position.distance(handler_post) <= self.profile.calls.range()
+    }
+
+    // Gives the dog a command from the handler's post, if it's close enough to hear it
+    pub fn call(&mut self, command: &DogCommand, position: Vec2, handler_post: Vec2) {
+        if !self.hears(position, handler_post) {
+            return;
+        }
+        if *command != self.command {
+            debug!("{} ({}): {}", self.profile.name, self.profile.calls.name(), command.name());
+        }
+        self.set_command(command);
+    }
 }
 
 impl Plugin for DogPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(GameState::Playing), spawn_dog)
-            .add_systems(Update, run_in_a_circle.run_if(in_state(GameState::Playing)));
+        app.init_resource::<HandlerPost>()
+            .init_resource::<Kennel>()
+            .init_resource::<SelectedDog>()
+            .add_systems(OnEnter(GameState::Playing), spawn_dog)
+            .add_systems(
+                FixedUpdate,
+                move_dog
+                    .in_set(SimulationSet::Step)
+                    .run_if(in_state(GameState::Playing)),
+            );
     }
 }
 
-fn spawn_dog(mut commands: Commands, textures: Res<TextureAssets>) {
-    commands
-        .spawn(SpriteBundle {
-            texture: textures.dog.clone(),
-            transform: Transform::from_translation(Vec3::new(0., 0., 1.)).with_scale(Vec3::new(0.3, 0.3, 0.5)),
-            ..Default::default()
-        }).insert(Dog {command: Away });
+// The dogs start side by side at the handler's post unless the level says otherwise
+fn spawn_dog(
+    mut commands: Commands,
+    textures: Option<Res<TextureAssets>>,
+    level: Option<Res<CurrentLevel>>,
+    handler_post: Res<HandlerPost>,
+    kennel: Res<Kennel>,
+) {
+    let start = level.and_then(|level| level.0.dog_start).unwrap_or(handler_post.0);
+    let first = -DOG_SPACING * (kennel.0.len().max(1) - 1) as f32 / 2.;
+
+    for (number, profile) in kennel.0.iter().enumerate() {
+        let position = start + Vec2::new(first + DOG_SPACING * number as f32, 0.);
+        commands
+            .spawn(SpriteBundle {
+                texture: textures.as_ref().map(|textures| textures.dog.clone()).unwrap_or_default(),
+                transform: Transform::from_translation(position.extend(1.)).with_scale(Vec3::new(0.3, 0.3, 0.5)),
+                ..Default::default()
+            })
+            .insert(Interpolated::new(position.extend(1.)))
+            .insert((Dog::new(number, profile.clone()), RunEntity));
+    }
 }
 
-fn listen_to_commands(
-    actions: Res<Actions>,
-    mut dog_query: Query<&mut Dog>,
-) {
+// Heads towards `target`, stopping once the dog has arrived
+fn run_to(position: Vec2, target: Vec2) -> Vec2 {
+    if position.distance(target) <= ARRIVE_DISTANCE {
+        Vec2::ZERO
+    } else {
+        (target - position).normalize_or_zero()
+    }
+}
 
+// Works out which way the dog wants to go for its current command, relative to the flock.
+// The result is a unit direction, or zero if the dog should stay put.
+fn dog_heading(dog: &Dog, position: Vec2, flock: &Flock, handler_post: Vec2) -> Vec2 {
+    let offset = position - flock.centre;
+    let distance = offset.length();
+    let outward = offset.normalize_or_zero();
+
+    match dog.command {
+        // "Easy" and "Steady" never reach here, but a dog left holding one stays put rather than crash the run
+        LayDown | Easy | Steady => Vec2::ZERO,
+        ComeBye | Away => {
+            // Clockwise tangent for come bye, anticlockwise for away
+            let tangent = match dog.command {
+                ComeBye => Vec2::new(outward.y, -outward.x),
+                _ => Vec2::new(-outward.y, outward.x),
+            };
+            // Drift back out (or in) towards the working distance while flanking
+            let correction = outward * (dog.working_distance - distance) / dog.working_distance * FLANK_CORRECTION;
+            (tangent + correction).normalize_or_zero()
+        }
+        WalkOn => {
+            if distance <= WALK_ON_STOP_DISTANCE {
+                Vec2::ZERO
+            } else {
+                -outward
+            }
+        }
+        ThatllDo => run_to(position, handler_post),
+        LookBack => {
+            // Get round behind the straggler so it gets pushed back towards the flock
+            let behind = (flock.straggler - flock.centre).normalize_or_zero();
+            run_to(position, flock.straggler + behind * dog.working_distance * 0.5)
+        }
+        GetOut => {
+            if distance >= dog.working_distance {
+                Vec2::ZERO
+            } else {
+                outward
+            }
+        }
+        Here => {
+            let handler_side = (handler_post - flock.centre).normalize_or_zero();
+            run_to(position, flock.centre + handler_side * dog.working_distance)
+        }
+    }
 }
 
-fn run_in_a_circle(
-    time: Res<Time>,
+pub fn move_dog(
+    fixed_time: Res<FixedTime>,
+    flock: Res<Flock>,
+    handler_post: Res<HandlerPost>,
+    field: Res<Field>,
+    obstacle_query: Query<&Obstacle>,
     mut dog_query: Query<(&mut Transform, &Dog)>,
 ) {
-    let speed = 20.;
+    let obstacles: Vec<&Obstacle> = obstacle_query.iter().collect();
 
-    let elapsed_time = time.raw_elapsed_seconds() as f32;
-    let sin = elapsed_time.sin() * 0.1;
-    let cos = elapsed_time.cos() * 0.1;
+    for (mut transform, dog) in &mut dog_query {
+        let position = transform.translation.truncate();
+        let heading = dog_heading(dog, position, &flock, handler_post.0);
+        let ground = field.tile_at(position).unwrap_or_default().dog_speed();
+        let movement = heading * DOG_SPEED * dog.pace * ground * fixed_time.period.as_secs_f32();
 
+        let resolved = resolve_movement(&obstacles, position, position + movement, DOG_RADIUS);
+        transform.translation = resolved.extend(transform.translation.z);
+    }
+}
 
-    let mut movement = Vec3::new(
-        &speed * sin,
-        &speed * cos,
-        0.,
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    for (mut transform, dog) in &mut dog_query {
-        match &dog.command {
-            LayDown => {
-                movement = Vec3::new(0., 0., 0.);
-            }
-            Away => {
-                transform.translation += Vec3::new(
-                    &speed * cos,
-                    &speed * sin,
-                    0.,
-                );
-            }
-            ComeBye => {
-                transform.translation += Vec3::new(
-                    &speed * sin,
-                    &speed * cos,
-                    0.,
-                );
-            }
-            _ => {}
-        }
+    #[test]
+    fn flanks_the_short_way_round() {
+        let east = Vec2::X;
+        // Anticlockwise from east is north
+        assert_eq!(flank_towards(east, Vec2::Y, 0.1), Away);
+        assert_eq!(flank_towards(east, -Vec2::Y, 0.1), ComeBye);
+        assert_eq!(flank_towards(east, Vec2::new(1., 0.05), 0.1), LayDown);
+        assert_eq!(flank_towards(east, Vec2::new(1., 0.05), 0.), Away);
+    }
+
+    #[test]
+    fn orders_that_change_nothing_are_not_kept() {
+        let mut dog = Dog::new(0, Kennel::default().0[0].clone());
+
+        dog.set_command(&ComeBye);
+        dog.set_command(&ComeBye);
+        dog.set_pace(0.5);
+        dog.set_pace(0.5);
+        // Back to full speed, so this one counts again
+        dog.set_command(&ComeBye);
+        dog.set_working_distance(dog.working_distance());
+
+        assert_eq!(
+            dog.take_orders(),
+            vec![DogOrder::Command(ComeBye), DogOrder::Pace(0.5), DogOrder::Command(ComeBye)]
+        );
+        assert!(dog.take_orders().is_empty());
     }
 
+    #[test]
+    fn get_out_counts_until_the_widest_flank() {
+        let mut dog = Dog::new(0, Kennel::default().0[0].clone());
+
+        for _ in 0..10 {
+            dog.set_command(&GetOut);
+        }
+
+        let widenings = (MAX_WORKING_DISTANCE - DEFAULT_WORKING_DISTANCE) / GET_OUT_STEP;
+        assert_eq!(dog.take_orders().len(), widenings.ceil() as usize);
+        assert_eq!(dog.working_distance(), MAX_WORKING_DISTANCE);
+    }
 }
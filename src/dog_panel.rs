@@ -0,0 +1,77 @@
+use bevy::prelude::*;
+
+use crate::dog::{Dog, Kennel, SelectedDog};
+use crate::loading::FontAssets;
+use crate::{GameState, RunEntity};
+
+const TEXT_COLOR: Color = Color::rgb(0.7, 0.7, 0.7);
+const SELECTED_COLOR: Color = Color::rgb(1.0, 0.85, 0.4);
+
+pub struct DogPanelPlugin;
+
+/// This plugin shows a line for each dog in the corner of the screen, with its number, name,
+/// how it takes commands and what it is doing. The dog the main keys command is highlighted.
+impl Plugin for DogPanelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Playing), spawn_dog_panel)
+            .add_systems(Update, update_dog_panel.run_if(in_state(GameState::Playing)));
+    }
+}
+
+// The line for the dog with this number
+#[derive(Component)]
+struct DogLabel(usize);
+
+// The dogs are spawned from the kennel at the same time, so there's a line for each of those
+fn spawn_dog_panel(mut commands: Commands, font_assets: Res<FontAssets>, kennel: Res<Kennel>) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(10.0),
+                top: Val::Px(10.0),
+                padding: UiRect::all(Val::Px(8.0)),
+                flex_direction: FlexDirection::Column,
+                ..Default::default()
+            },
+            background_color: Color::rgba(0.1, 0.1, 0.1, 0.6).into(),
+            ..Default::default()
+        })
+        .insert(RunEntity)
+        .with_children(|parent| {
+            for number in 0..kennel.0.len() {
+                parent
+                    .spawn(TextBundle::from_section(
+                        "",
+                        TextStyle {
+                            font: font_assets.fira_sans.clone(),
+                            font_size: 22.0,
+                            color: TEXT_COLOR,
+                        },
+                    ))
+                    .insert(DogLabel(number));
+            }
+        });
+}
+
+fn update_dog_panel(
+    selected: Res<SelectedDog>,
+    dog_query: Query<&Dog>,
+    mut label_query: Query<(&DogLabel, &mut Text)>,
+) {
+    for (label, mut text) in &mut label_query {
+        let Some(dog) = dog_query.iter().find(|dog| dog.number() == label.0) else {
+            continue;
+        };
+        let profile = dog.profile();
+        let section = &mut text.sections[0];
+        section.value = format!(
+            "{} {} ({}): {}",
+            dog.number() + 1,
+            profile.name,
+            profile.calls.name(),
+            dog.command().name()
+        );
+        section.style.color = if dog.number() == selected.0 { SELECTED_COLOR } else { TEXT_COLOR };
+    }
+}
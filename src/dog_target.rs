@@ -0,0 +1,127 @@
+use bevy::prelude::*;
+
+use crate::ai_handler::AiHandler;
+use crate::dog::{flank_towards, move_dog, Dog, DogCommand, HandlerPost, SelectedDog};
+use crate::loading::TextureAssets;
+use crate::sheep::Flock;
+use crate::simulation::SimulationSet;
+use crate::{GameState, RunEntity};
+
+// Once the dog is within this angle (in radians) of its target bearing it lies down there
+const TARGET_ARRIVE_ANGLE: f32 = 0.1;
+const MARKER_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.4);
+
+pub struct DogTargetPlugin;
+
+/// This plugin walks a dog to a point the player picked round the flock. It gives the dog
+/// "Come bye" or "Away", whichever is the short way round, and "Lie down" once it gets there,
+/// while a faint ghost of the dog marks the spot the selected dog is heading for.
+impl Plugin for DogTargetPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Playing), spawn_target_marker)
+            .add_systems(Update, show_target_marker.run_if(in_state(GameState::Playing)))
+            .add_systems(
+                FixedUpdate,
+                steer_to_target
+                    .before(move_dog)
+                    .in_set(SimulationSet::Step)
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+// Where round the flock a dog has been sent. The dog stops on this bearing from the flock centre,
+// at its working distance. Any other command given to the dog meanwhile calls the target off.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct DogTarget {
+    // Unit direction from the flock centre
+    bearing: Vec2,
+    // The last command given to get there
+    given: Option<DogCommand>,
+}
+
+impl DogTarget {
+    pub fn new(bearing: Vec2) -> Self {
+        Self {
+            bearing: bearing.normalize_or_zero(),
+            given: None,
+        }
+    }
+
+    pub fn bearing(&self) -> Vec2 {
+        self.bearing
+    }
+}
+
+#[derive(Component)]
+struct TargetMarker;
+
+pub fn steer_to_target(
+    mut commands: Commands,
+    flock: Res<Flock>,
+    handler_post: Res<HandlerPost>,
+    mut dog_query: Query<(Entity, &Transform, &mut Dog, &mut DogTarget), Without<AiHandler>>,
+) {
+    for (entity, transform, mut dog, mut target) in &mut dog_query {
+        // The player (or the stick) has told the dog something else since
+        if target.given.is_some_and(|given| given != *dog.command()) || target.bearing == Vec2::ZERO {
+            commands.entity(entity).remove::<DogTarget>();
+            continue;
+        }
+        if flock.count == 0 {
+            continue;
+        }
+
+        let position = transform.translation.truncate();
+        let command = flank_towards(position - flock.centre, target.bearing, TARGET_ARRIVE_ANGLE);
+
+        if target.given != Some(command) {
+            dog.call(&command, position, handler_post.0);
+            if *dog.command() == command {
+                target.given = Some(command);
+            }
+        }
+        if target.given == Some(DogCommand::LayDown) {
+            commands.entity(entity).remove::<DogTarget>();
+        }
+    }
+}
+
+fn spawn_target_marker(mut commands: Commands, textures: Option<Res<TextureAssets>>) {
+    commands
+        .spawn(SpriteBundle {
+            sprite: Sprite {
+                color: MARKER_COLOR,
+                ..Default::default()
+            },
+            texture: textures.as_ref().map(|textures| textures.dog.clone()).unwrap_or_default(),
+            transform: Transform::from_translation(Vec3::Z).with_scale(Vec3::new(0.3, 0.3, 0.5)),
+            visibility: Visibility::Hidden,
+            ..Default::default()
+        })
+        .insert((TargetMarker, RunEntity));
+}
+
+// The ghost sits where the selected dog will stop, and follows the flock as it moves
+fn show_target_marker(
+    flock: Res<Flock>,
+    selected: Res<SelectedDog>,
+    dog_query: Query<(&Dog, &DogTarget)>,
+    mut marker_query: Query<(&mut Transform, &mut Visibility), With<TargetMarker>>,
+) {
+    let target = dog_query
+        .iter()
+        .find(|(dog, _)| dog.number() == selected.0)
+        .filter(|_| flock.count > 0)
+        .map(|(dog, target)| flock.centre + target.bearing() * dog.working_distance());
+
+    for (mut transform, mut visibility) in &mut marker_query {
+        match target {
+            Some(target) => {
+                transform.translation = target.extend(transform.translation.z);
+                *visibility = Visibility::Inherited;
+            }
+            None => *visibility = Visibility::Hidden,
+        }
+    }
+}
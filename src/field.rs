@@ -1,138 +1,186 @@
 use bevy::prelude::*;
 
-use crate::GameState;
-use crate::loading::TextureAssets;
+use crate::level::Level;
+use crate::rng::GameSeed;
+use crate::terrain::{generate_terrain, Terrain};
+
+// World size of the field when neither a level nor an up front `Field` says otherwise
+const DEFAULT_FIELD_WIDTH: f32 = 2000.0;
+const DEFAULT_FIELD_HEIGHT: f32 = 2000.0;
+// Size of a tile in world units when the field isn't laid out by a level
+pub const DEFAULT_TILE_SIZE: f32 = 64.0;
+
+// Column and row steps to the eight tiles around a tile, rows counting down the field
+const NEIGHBOUR_OFFSETS: [IVec2; 8] = [
+    IVec2::new(-1, -1),
+    IVec2::new(0, -1),
+    IVec2::new(1, -1),
+    IVec2::new(-1, 0),
+    IVec2::new(1, 0),
+    IVec2::new(-1, 1),
+    IVec2::new(0, 1),
+    IVec2::new(1, 1),
+];
 
 pub struct FieldPlugin;
 
+// The playing area, centred on the world origin, and the grid of terrain tiles covering it.
+// Tiles are addressed as (column, row) counting from the top left.
 #[derive(Resource)]
 pub struct Field {
     pub width: f32,
     pub height: f32,
-    pub origin: (f32, f32),
-    pub sprites: Vec<Entity>,
+    pub tile_size: f32,
+    pub columns: usize,
+    pub rows: usize,
+    // One per tile, row by row from the top left
+    pub terrain: Vec<Terrain>,
 }
 
 impl Field {
-    pub fn new(width: f32, height: f32) -> Self {
-        let origin = (&width / -2.0, &height / 2.0);
-        println!("Field origin: {:?}", origin);
-
-        Self { width, height, origin, sprites: vec![] }
+    // A field of plain grass
+    pub fn new(width: f32, height: f32, tile_size: f32) -> Self {
+        let columns = (width / tile_size).ceil() as usize;
+        let rows = (height / tile_size).ceil() as usize;
+
+        Self {
+            width,
+            height,
+            tile_size,
+            columns,
+            rows,
+            terrain: vec![Terrain::default(); columns * rows],
+        }
     }
 
-    pub fn update_size(&mut self, width: f32, height: f32) {
-        self.width = width;
-        self.height = height;
-        self.origin = (&width / -2.0, &height / 2.0);
+    // A field of varied ground, the same every time for the same seed
+    pub fn generated(width: f32, height: f32, tile_size: f32, seed: u64) -> Self {
+        let mut field = Self::new(width, height, tile_size);
+        field.terrain = generate_terrain(field.columns, field.rows, seed);
+        field
     }
 
-    pub fn width_in_tiles(&self, tile_width: Option<f32>) -> i32 {
-        let tile_width = match tile_width {
-            Some(th) => th,
-            None => 64.
-        };
-        ((self.width.clone() / tile_width).ceil() + 1.) as i32
+    // Levels without painted terrain get generated ground
+    pub fn from_level(level: &Level, seed: u64) -> Self {
+        Self {
+            width: level.width,
+            height: level.height,
+            tile_size: level.tile_size,
+            columns: level.columns,
+            rows: level.rows,
+            terrain: level
+                .terrain
+                .clone()
+                .unwrap_or_else(|| generate_terrain(level.columns, level.rows, seed)),
+        }
+    }
 
+    // Whether a tile, given as (column, row) from the top left, is on the field
+    pub fn in_bounds(&self, tile: IVec2) -> bool {
+        tile.x >= 0 && tile.y >= 0 && (tile.x as usize) < self.columns && (tile.y as usize) < self.rows
     }
 
-    pub fn height_in_tiles(&self, tile_height: Option<f32>) -> i32 {
-        let tile_height = match tile_height {
-            Some(th) => th,
-            None => 64.
-        };
-        ((self.height.clone() / tile_height).ceil() + 1.) as i32
+    // The tile a point in the world falls on, or none off the edge of the tiles
+    pub fn world_to_tile(&self, position: Vec2) -> Option<UVec2> {
+        let from_top_left = (position - self.top_left()) * Vec2::new(1., -1.) / self.tile_size;
+        let tile = from_top_left.floor().as_ivec2();
+        self.in_bounds(tile).then(|| tile.as_uvec2())
+    }
 
+    // The centre of a tile in the world
+    pub fn tile_to_world(&self, tile: UVec2) -> Vec2 {
+        let from_top_left = (tile.as_vec2() + 0.5) * self.tile_size;
+        self.top_left() + from_top_left * Vec2::new(1., -1.)
     }
 
-    pub fn tile_offset(&self, tile_width: Option<f32>, tile_height: Option<f32>) -> (f32, f32) {
-        let tile_height = match tile_height {
-            Some(th) => th,
-            None => 64.
-        };
-        let tile_width = match tile_width {
-            Some(tw) => tw,
-            None => 64.
-        };
-
-        let overhang_x = (self.width_in_tiles(Some(tile_width)) as f32)
-            * &tile_width
-            - &self.width;
-        let overhang_y = (self.height_in_tiles(Some(tile_height)) as f32)
-            * &tile_height
-            - &self.height;
-
-        (overhang_x / -2., overhang_y / -2.)
+    pub fn terrain_at(&self, tile: UVec2) -> Terrain {
+        self.terrain[tile.y as usize * self.columns + tile.x as usize]
     }
 
-    pub fn tile_origin(&self, tile_width: Option<f32>, tile_height: Option<f32>) -> (f32, f32) {
-        let (x, y) = self.tile_offset(tile_width, tile_height);
-        (&self.origin.0 + x, &self.origin.1 - y)
+    // The terrain under a point in the world, or none off the edge of the tiles
+    pub fn tile_at(&self, position: Vec2) -> Option<Terrain> {
+        self.world_to_tile(position).map(|tile| self.terrain_at(tile))
     }
 
-    pub fn despawn_tiles(&mut self, commands: &mut Commands) {
-        for sprite in self.sprites.iter() {
-            commands.entity(*sprite).despawn();
-        }
-        self.sprites.clear();
+    // The tiles around a tile, diagonals included, leaving out any off the edge
+    pub fn neighbours(&self, tile: UVec2) -> impl Iterator<Item = UVec2> + '_ {
+        NEIGHBOUR_OFFSETS
+            .into_iter()
+            .map(move |offset| tile.as_ivec2() + offset)
+            .filter(|neighbour| self.in_bounds(*neighbour))
+            .map(|neighbour| neighbour.as_uvec2())
     }
 
-    pub fn spawn_tiles(&mut self, commands: &mut Commands, textures: &Res<TextureAssets>) {
-
-        let _ = &self.despawn_tiles(commands);
-
-        let (x, y) = self.tile_origin(None, None);
-        let mut x = x;
-        let mut y = y;
-
-        let mut i = 0;
-        while i < self.width_in_tiles(None) {
-            let mut j = 0;
-            while j < self.height_in_tiles(None) {
-                self.sprites.push(commands.spawn(
-                    SpriteBundle {
-                        transform: Transform::from_translation(Vec3::new(x.clone() , y.clone(), 0.)),
-                        texture: textures.texture_tile_grass_1.clone(),
-                        ..Default::default()
-                    }
-                ).id());
-                y -= 64.;
-                j += 1;
-            }
-            x += 64.;
-            y = self.tile_origin(None, None).1;
-            i += 1;
-        }
+    // Top left corner of the tiles; they are centred on the field even when they overhang its edges
+    fn top_left(&self) -> Vec2 {
+        Vec2::new(-(self.columns as f32), self.rows as f32) * self.tile_size / 2.
     }
 }
 
 impl Plugin for FieldPlugin {
     fn build(&self, app: &mut App) {
-        app
-            .insert_resource(Field::new(0.0, 0.0))
-            .add_systems(Update, spawn_field.run_if(in_state(GameState::Playing)));
-
+        // A field inserted up front (e.g. by the headless simulation) keeps its size
+        if !app.world.contains_resource::<Field>() {
+            let seed = app.world.get_resource::<GameSeed>().map_or(0, |seed| seed.0);
+            app.insert_resource(Field::generated(DEFAULT_FIELD_WIDTH, DEFAULT_FIELD_HEIGHT, DEFAULT_TILE_SIZE, seed));
+        }
     }
 }
 
-fn spawn_field(mut commands: Commands, textures: Res<TextureAssets>, mut windows: Query<&mut Window>, mut field: ResMut<Field>) {
-    let window = windows.iter_mut().next().unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if !window.is_changed() {
-        return;
+    #[test]
+    fn tile_centres_round_trip() {
+        let field = Field::new(640., 640., 64.);
+
+        for tile in [UVec2::new(0, 0), UVec2::new(3, 7), UVec2::new(9, 9)] {
+            let centre = field.tile_to_world(tile);
+            assert_eq!(field.world_to_tile(centre), Some(tile));
+            assert_eq!(field.tile_to_world(field.world_to_tile(centre).unwrap()), centre);
+        }
+        // Rows count down the field from the top left
+        assert_eq!(field.tile_to_world(UVec2::new(0, 0)), Vec2::new(-288., 288.));
     }
 
-    println!("Window changed: width: {}, height: {}", window.width(), window.height());
+    #[test]
+    fn the_far_edges_are_off_the_field() {
+        let field = Field::new(640., 640., 64.);
+
+        assert_eq!(field.world_to_tile(Vec2::new(-320., 320.)), Some(UVec2::new(0, 0)));
+        assert_eq!(field.world_to_tile(Vec2::new(320., 0.)), None);
+        assert_eq!(field.world_to_tile(Vec2::new(0., -320.)), None);
+        assert_eq!(field.world_to_tile(Vec2::new(319.9, -319.9)), Some(UVec2::new(9, 9)));
+        assert_eq!(field.world_to_tile(Vec2::new(-320.1, 0.)), None);
+        assert_eq!(field.world_to_tile(Vec2::new(0., 320.1)), None);
+        assert_eq!(field.tile_at(Vec2::new(320., 0.)), None);
+        assert_eq!(field.tile_at(Vec2::ZERO), Some(Terrain::default()));
+    }
 
-    field.update_size(window.width(), window.height());
+    #[test]
+    fn corner_tiles_only_have_neighbours_on_the_field() {
+        let field = Field::new(640., 640., 64.);
 
-    println!("Field size in tiles: width: {}, height: {}", field.width_in_tiles(None), field.height_in_tiles(None));
+        let mut corner: Vec<UVec2> = field.neighbours(UVec2::new(0, 0)).collect();
+        corner.sort_by_key(|tile| (tile.y, tile.x));
+        assert_eq!(corner, vec![UVec2::new(1, 0), UVec2::new(0, 1), UVec2::new(1, 1)]);
 
-    field.spawn_tiles(&mut commands, &textures);
-}
+        assert_eq!(field.neighbours(UVec2::new(9, 9)).count(), 3);
+        assert_eq!(field.neighbours(UVec2::new(0, 5)).count(), 5);
+        assert_eq!(field.neighbours(UVec2::new(5, 5)).count(), 8);
+    }
 
-fn find_bottom_right(window: &Window) -> Vec3 {
-    Vec3::new(window.width() / 2.0, window.height() / -2.0, 0.0)
+    #[test]
+    fn other_tile_sizes_are_honoured() {
+        let field = Field::new(300., 200., 50.);
+
+        assert_eq!((field.columns, field.rows), (6, 4));
+        assert_eq!(field.terrain.len(), 24);
+        assert_eq!(field.tile_to_world(UVec2::new(5, 3)), Vec2::new(125., -75.));
+        assert_eq!(field.world_to_tile(Vec2::new(149., -99.)), Some(UVec2::new(5, 3)));
+        assert_eq!(field.world_to_tile(Vec2::new(101., -51.)), Some(UVec2::new(5, 3)));
+        assert_eq!(field.world_to_tile(Vec2::new(99., -51.)), Some(UVec2::new(4, 3)));
+        assert!(field.neighbours(UVec2::new(5, 3)).all(|tile| tile.x < 6 && tile.y < 4));
+    }
 }
-
-
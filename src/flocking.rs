@@ -0,0 +1,158 @@
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy::utils::BoxedFuture;
+use serde::{Deserialize, Serialize};
+
+use crate::flocking_model::FlockingModelKind;
+use crate::GameState;
+
+pub struct FlockingPlugin;
+
+/// This plugin owns the tunable flocking constants.
+/// They start out at their built-in defaults and are replaced by the contents of
+/// `assets/sheep.flocking.ron` whenever that file is loaded or edited on disk,
+/// unless some were inserted before the game plugins (e.g. by a replay), which stay put.
+/// Edits made during a run wait until it's over, so the run plays back with the params it was recorded with.
+impl Plugin for FlockingPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<FlockingParams>() {
+            app.init_resource::<FlockingParams>()
+                .add_systems(Update, apply_flocking_params);
+        }
+
+        app.add_asset::<FlockingParams>()
+            .init_asset_loader::<FlockingParamsLoader>();
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, TypeUuid, TypePath, Debug, Clone, PartialEq)]
+#[uuid = "5b0c6a1e-8d4f-4c3a-9a57-2f1e6d3b7c90"]
+#[serde(default)]
+pub struct FlockingParams {
+    // How the sheep react to each other, see `FlockingModelKind`
+    pub model: FlockingModelKind,
+    // Upper bound on each velocity component
+    pub max_speed: f32,
+    // Converts velocity into world units per second
+    pub speed: f32,
+    // Velocity nudge applied when a sheep strays towards the edge of the field
+    pub boundary_damping: f32,
+    // Sheep closer than this push each other apart
+    pub protected_distance: f32,
+    // Sheep closer than this (but outside the protected distance) align and cohere
+    pub visible_distance: f32,
+    pub avoid_factor: f32,
+    pub align_factor: f32,
+    pub centering_factor: f32,
+    // Strength of the random nudge added every update
+    pub wander_force: f32,
+    // Sheep react to any dog closer than this
+    pub flight_zone_radius: f32,
+    // Strength of the push away from a dog right next to the sheep, fading to nothing at the edge of the flight zone
+    pub flee_factor: f32,
+    // How much extra speed and cohesion a sheep gains with a dog right on top of it,
+    // as a fraction of `max_speed` and `centering_factor`
+    pub pressure_speed_boost: f32,
+    pub pressure_cohesion_boost: f32,
+    // How strongly sheep drift towards better grazing, fading out as a dog closes in
+    pub grazing_factor: f32,
+    // Sheep start steering away from obstacles this far off, by up to `obstacle_factor` a tick
+    pub obstacle_distance: f32,
+    pub obstacle_factor: f32,
+    // How far sheep can see at all, for the models that pick neighbours by count rather than distance
+    pub sight_distance: f32,
+    // How many of the nearest sheep the topological model follows
+    pub topological_neighbours: usize,
+    // How many of the nearest sheep a worried sheep heads for the middle of in the Strömbom model,
+    // how hard it heads there, and how hard it pushes away from sheep inside the protected distance
+    pub strombom_neighbours: usize,
+    pub strombom_attraction: f32,
+    pub strombom_repulsion: f32,
+    // Outer edges of the Couzin zones of orientation and attraction, repulsion being the protected distance,
+    // and how much of the way towards its desired heading a sheep turns each tick
+    pub couzin_orientation_distance: f32,
+    pub couzin_attraction_distance: f32,
+    pub couzin_turn_rate: f32,
+}
+
+impl Default for FlockingParams {
+    fn default() -> Self {
+        Self {
+            model: FlockingModelKind::Boids,
+            max_speed: 2.0,
+            speed: 150.0,
+            boundary_damping: 0.1,
+            protected_distance: 50.0,
+            visible_distance: 100.0,
+            avoid_factor: 0.002,
+            align_factor: 0.01,
+            centering_factor: 0.0001,
+            wander_force: 0.1,
+            flight_zone_radius: 200.0,
+            flee_factor: 0.3,
+            pressure_speed_boost: 1.0,
+            pressure_cohesion_boost: 10.0,
+            grazing_factor: 0.01,
+            obstacle_distance: 60.0,
+            obstacle_factor: 0.3,
+            sight_distance: 300.0,
+            topological_neighbours: 7,
+            strombom_neighbours: 10,
+            strombom_attraction: 0.1,
+            strombom_repulsion: 0.2,
+            couzin_orientation_distance: 100.0,
+            couzin_attraction_distance: 250.0,
+            couzin_turn_rate: 0.1,
+        }
+    }
+}
+
+#[derive(Default)]
+struct FlockingParamsLoader;
+
+impl AssetLoader for FlockingParamsLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let params = ron::de::from_bytes::<FlockingParams>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(params));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        // The loader goes by everything after the first dot, so other `.ron` assets are left alone
+        &["flocking.ron"]
+    }
+}
+
+// Copies the loaded asset into the resource, both on first load and on every hot reload
+fn apply_flocking_params(
+    mut events: EventReader<AssetEvent<FlockingParams>>,
+    assets: Res<Assets<FlockingParams>>,
+    mut params: ResMut<FlockingParams>,
+    state: Res<State<GameState>>,
+    mut pending: Local<Option<Handle<FlockingParams>>>,
+) {
+    for event in events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                *pending = Some(handle.clone_weak());
+            }
+            AssetEvent::Removed { .. } => {}
+        }
+    }
+
+    // A run is recorded with the params it started with, the latest edit is held until it ends
+    if *state.get() == GameState::Playing {
+        return;
+    }
+    if let Some(loaded) = pending.take().and_then(|handle| assets.get(&handle)) {
+        info!("Applying flocking params: {:?}", loaded);
+        *params = loaded.clone();
+    }
+}
@@ -0,0 +1,328 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::flocking::FlockingParams;
+
+// Which flocking model the sheep follow. It's the `model` in `sheep.flocking.ron` unless one is inserted
+// before the game plugins (e.g. from `--flocking`), and the flock switches over on the next tick
+// whenever the resource changes.
+#[derive(Resource, Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FlockingModelKind {
+    #[default]
+    Boids,
+    Strombom,
+    Couzin,
+    Topological,
+}
+
+impl FlockingModelKind {
+    pub const ALL: [FlockingModelKind; 4] = [
+        FlockingModelKind::Boids,
+        FlockingModelKind::Strombom,
+        FlockingModelKind::Couzin,
+        FlockingModelKind::Topological,
+    ];
+
+    // What the model is called on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            FlockingModelKind::Boids => "boids",
+            FlockingModelKind::Strombom => "strombom",
+            FlockingModelKind::Couzin => "couzin",
+            FlockingModelKind::Topological => "topological",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn model(self) -> &'static dyn FlockingModel {
+        match self {
+            FlockingModelKind::Boids => &Boids,
+            FlockingModelKind::Strombom => &Strombom,
+            FlockingModelKind::Couzin => &Couzin,
+            FlockingModelKind::Topological => &Topological,
+        }
+    }
+}
+
+// Another sheep, as seen by the one deciding where to go
+#[derive(Debug, Clone, Copy)]
+pub struct Neighbour {
+    pub position: Vec2,
+    pub velocity: Vec2,
+}
+
+// What a sheep knows when it decides how the rest of the flock moves it
+#[derive(Debug)]
+pub struct Neighbourhood<'a> {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    // How close a dog is, from 0 with none in the flight zone to 1 with one right on top of it
+    pub pressure: f32,
+    // Every other sheep around it, possibly a few beyond the model's sight distance
+    pub neighbours: &'a [Neighbour],
+}
+
+// The rules sheep use to react to each other. Dogs, grazing, obstacles and wandering are
+// handled the same whichever model is in use.
+pub trait FlockingModel: Send + Sync {
+    // How far off another sheep can be and still be worth passing to `steer`
+    fn sight_distance(&self, params: &FlockingParams) -> f32;
+
+    // The change to the sheep's velocity this tick from the sheep around it
+    fn steer(&self, sheep: &Neighbourhood, params: &FlockingParams) -> Vec2;
+}
+
+// Reynolds' boids: keep apart from sheep inside the protected distance, match the heading of the
+// rest within the visible distance and close in on them
+pub struct Boids;
+
+impl FlockingModel for Boids {
+    fn sight_distance(&self, params: &FlockingParams) -> f32 {
+        params.visible_distance
+    }
+
+    fn steer(&self, sheep: &Neighbourhood, params: &FlockingParams) -> Vec2 {
+        let within_sight = sheep
+            .neighbours
+            .iter()
+            .filter(|other| sheep.position.distance(other.position) <= params.visible_distance);
+        boids_steering(sheep, within_sight, params)
+    }
+}
+
+// Boids rules over a fixed number of the nearest sheep however far off they are, rather than every
+// sheep within a distance, as flocking birds have been found to do (Ballerini et al. 2008)
+pub struct Topological;
+
+impl FlockingModel for Topological {
+    fn sight_distance(&self, params: &FlockingParams) -> f32 {
+        params.sight_distance
+    }
+
+    fn steer(&self, sheep: &Neighbourhood, params: &FlockingParams) -> Vec2 {
+        let nearest = nearest_neighbours(sheep, params.topological_neighbours, params.sight_distance);
+        boids_steering(sheep, nearest.iter(), params)
+    }
+}
+
+// Strömbom et al. (2014): sheep push away from any sheep right next to them, and only head for
+// the middle of their nearest neighbours once a dog is close enough to worry them
+pub struct Strombom;
+
+impl FlockingModel for Strombom {
+    fn sight_distance(&self, params: &FlockingParams) -> f32 {
+        params.sight_distance
+    }
+
+    fn steer(&self, sheep: &Neighbourhood, params: &FlockingParams) -> Vec2 {
+        let repulsion: Vec2 = sheep
+            .neighbours
+            .iter()
+            .map(|other| sheep.position - other.position)
+            .filter(|away| away.length() <= params.protected_distance)
+            .map(|away| away.normalize_or_zero())
+            .sum();
+        let mut steering = repulsion.normalize_or_zero() * params.strombom_repulsion;
+
+        if sheep.pressure > 0.0 {
+            let nearest = nearest_neighbours(sheep, params.strombom_neighbours, params.sight_distance);
+            if !nearest.is_empty() {
+                let local_centre = nearest.iter().map(|other| other.position).sum::<Vec2>() / nearest.len() as f32;
+                steering += (local_centre - sheep.position).normalize_or_zero() * params.strombom_attraction;
+            }
+        }
+
+        steering
+    }
+}
+
+// Couzin et al. (2002): a sheep with another inside its zone of repulsion (the protected distance)
+// only moves away; otherwise it lines up with those in its zone of orientation and heads for
+// those further out in its zone of attraction
+pub struct Couzin;
+
+impl FlockingModel for Couzin {
+    fn sight_distance(&self, params: &FlockingParams) -> f32 {
+        params.couzin_attraction_distance
+    }
+
+    fn steer(&self, sheep: &Neighbourhood, params: &FlockingParams) -> Vec2 {
+        let mut repel = Vec2::ZERO;
+        let mut orient = Vec2::ZERO;
+        let mut attract = Vec2::ZERO;
+
+        for other in sheep.neighbours {
+            let offset = other.position - sheep.position;
+            let distance = offset.length();
+            if distance <= params.protected_distance {
+                repel -= offset.normalize_or_zero();
+            } else if distance <= params.couzin_orientation_distance {
+                orient += other.velocity.normalize_or_zero();
+            } else if distance <= params.couzin_attraction_distance {
+                attract += offset.normalize_or_zero();
+            }
+        }
+
+        let desired = if repel != Vec2::ZERO {
+            repel
+        } else {
+            orient.normalize_or_zero() + attract.normalize_or_zero()
+        };
+        let Some(direction) = desired.try_normalize() else {
+            return Vec2::ZERO;
+        };
+
+        // Turn part of the way towards heading off in that direction at full speed
+        (direction * params.max_speed - sheep.velocity) * params.couzin_turn_rate
+    }
+}
+
+// Separation from the sheep inside the protected distance, alignment with and cohesion towards the rest
+fn boids_steering<'a>(
+    sheep: &Neighbourhood,
+    others: impl Iterator<Item = &'a Neighbour>,
+    params: &FlockingParams,
+) -> Vec2 {
+    let mut close_d = Vec2::ZERO;
+    let mut vel_sum = Vec2::ZERO;
+    let mut pos_sum = Vec2::ZERO;
+    let mut count = 0;
+
+    for other in others {
+        let d = sheep.position - other.position;
+        if d.length() <= params.protected_distance {
+            close_d += d;
+        } else {
+            count += 1;
+            vel_sum += other.velocity;
+            pos_sum += other.position;
+        }
+    }
+
+    let mut steering = Vec2::ZERO;
+    if count > 0 {
+        // A dog close by makes the sheep bunch up
+        let centering_factor = params.centering_factor * (1.0 + sheep.pressure * params.pressure_cohesion_boost);
+
+        steering += (vel_sum / count as f32 - sheep.velocity) * params.align_factor;
+        steering += (pos_sum / count as f32 - sheep.position) * centering_factor;
+    }
+
+    steering + close_d * params.avoid_factor
+}
+
+// Up to `count` of the closest sheep within `sight_distance`, nearest first
+fn nearest_neighbours(sheep: &Neighbourhood, count: usize, sight_distance: f32) -> Vec<Neighbour> {
+    let mut nearest: Vec<Neighbour> = sheep
+        .neighbours
+        .iter()
+        .filter(|other| sheep.position.distance(other.position) <= sight_distance)
+        .copied()
+        .collect();
+    nearest.sort_by(|a, b| {
+        let a = sheep.position.distance_squared(a.position);
+        let b = sheep.position.distance_squared(b.position);
+        a.total_cmp(&b)
+    });
+    nearest.truncate(count);
+    nearest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn neighbour(x: f32, y: f32, velocity: Vec2) -> Neighbour {
+        Neighbour {
+            position: Vec2::new(x, y),
+            velocity,
+        }
+    }
+
+    fn steer(kind: FlockingModelKind, neighbours: &[Neighbour], pressure: f32, params: &FlockingParams) -> Vec2 {
+        let sheep = Neighbourhood {
+            position: Vec2::ZERO,
+            velocity: Vec2::ZERO,
+            pressure,
+            neighbours,
+        };
+        kind.model().steer(&sheep, params)
+    }
+
+    #[test]
+    fn every_model_keeps_apart_from_a_sheep_too_close() {
+        let params = FlockingParams::default();
+        let crowding = [neighbour(params.protected_distance / 2., 0., Vec2::ZERO)];
+
+        for kind in FlockingModelKind::ALL {
+            let steering = steer(kind, &crowding, 0., &params);
+            assert!(steering.x < 0., "{} steered {:?} towards a sheep too close", kind.name(), steering);
+            assert_eq!(steering.y, 0., "{} steered {:?} sideways", kind.name(), steering);
+        }
+    }
+
+    #[test]
+    fn boids_ignore_sheep_out_of_sight() {
+        let params = FlockingParams::default();
+        let far = [neighbour(params.visible_distance + 1., 0., Vec2::Y)];
+        assert_eq!(steer(FlockingModelKind::Boids, &far, 0., &params), Vec2::ZERO);
+    }
+
+    #[test]
+    fn topological_follows_only_its_nearest_neighbours() {
+        let params = FlockingParams {
+            topological_neighbours: 3,
+            ..Default::default()
+        };
+        // The nearest few head up the field, many more further off head down it
+        let flock: Vec<Neighbour> = (1..=10)
+            .map(|i| {
+                let velocity = if i <= 3 { Vec2::Y } else { -Vec2::Y * 100. };
+                neighbour(params.protected_distance + i as f32 * 10., 0., velocity)
+            })
+            .collect();
+
+        let sheep = Neighbourhood {
+            position: Vec2::ZERO,
+            velocity: Vec2::ZERO,
+            pressure: 0.,
+            neighbours: &flock,
+        };
+        assert_eq!(nearest_neighbours(&sheep, params.topological_neighbours, params.sight_distance).len(), 3);
+        assert!(steer(FlockingModelKind::Topological, &flock, 0., &params).y > 0.);
+        // Boids would follow everything in sight, and head down with the many
+        assert!(steer(FlockingModelKind::Boids, &flock, 0., &params).y < 0.);
+    }
+
+    #[test]
+    fn strombom_sheep_only_gather_when_worried() {
+        let params = FlockingParams::default();
+        let away = [neighbour(200., 0., Vec2::ZERO)];
+
+        assert_eq!(steer(FlockingModelKind::Strombom, &away, 0., &params), Vec2::ZERO);
+        let worried = steer(FlockingModelKind::Strombom, &away, 0.5, &params);
+        assert!(worried.x > 0.);
+        assert_eq!(worried.length(), params.strombom_attraction);
+    }
+
+    #[test]
+    fn couzin_sheep_line_up_then_close_in() {
+        let params = FlockingParams::default();
+        let orienting = (params.protected_distance + params.couzin_orientation_distance) / 2.;
+        let attracting = (params.couzin_orientation_distance + params.couzin_attraction_distance) / 2.;
+
+        let alongside = steer(FlockingModelKind::Couzin, &[neighbour(orienting, 0., Vec2::Y)], 0., &params);
+        assert_eq!(alongside.x, 0.);
+        assert!(alongside.y > 0.);
+
+        let ahead = steer(FlockingModelKind::Couzin, &[neighbour(attracting, 0., -Vec2::Y)], 0., &params);
+        assert!(ahead.x > 0.);
+        assert_eq!(ahead.y, 0.);
+
+        let beyond = [neighbour(params.couzin_attraction_distance + 1., 0., Vec2::Y)];
+        assert_eq!(steer(FlockingModelKind::Couzin, &beyond, 0., &params), Vec2::ZERO);
+    }
+}
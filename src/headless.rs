@@ -0,0 +1,193 @@
+use bevy::app::App;
+use bevy::input::InputPlugin;
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+
+use crate::actions::{ActionsPlugin, InputMap, WhistleTemplates};
+use crate::ai_handler::{AiHandlerPlugin, Handler};
+use crate::dog::{DogPlugin, Kennel};
+use crate::field::{Field, FieldPlugin, DEFAULT_TILE_SIZE};
+use crate::flocking::{FlockingParams, FlockingPlugin};
+use crate::flocking_model::FlockingModelKind;
+use crate::level::{CurrentLevel, Level, LevelPlugin};
+use crate::obstacle::ObstaclePlugin;
+use crate::pen::PenPlugin;
+use crate::replay::{Replay, ReplayPlugin, ReplayRecorder};
+use crate::rng::{GameSeed, RngPlugin};
+use crate::sheep::{Flock, Sheep, SheepCount, SheepPlugin};
+use crate::simulation::{SimulationPlugin, SimulationTick, TickRate};
+use crate::trial::{GameMode, Scorecard, TrialPlugin};
+use crate::GameState;
+
+/// Settings for a headless run. The field size comes from here instead of a window,
+/// unless a level is given, which brings its own size.
+#[derive(Debug, Clone)]
+pub struct HeadlessConfig {
+    pub field_width: f32,
+    pub field_height: f32,
+    pub tile_size: f32,
+    pub seed: u64,
+    pub tick_rate: f32,
+    pub sheep_count: i32,
+    pub flocking_model: FlockingModelKind,
+    pub flocking_params: FlockingParams,
+    pub handler: Handler,
+    pub kennel: Kennel,
+    pub mode: GameMode,
+    pub level: Option<Level>,
+    // Played back instead of recording the run, see `HeadlessConfig::from_replay`
+    pub replay: Option<Replay>,
+}
+
+impl Default for HeadlessConfig {
+    fn default() -> Self {
+        Self {
+            field_width: 2000.0,
+            field_height: 2000.0,
+            tile_size: DEFAULT_TILE_SIZE,
+            seed: 0,
+            tick_rate: 60.0,
+            sheep_count: SheepCount::default().0,
+            flocking_model: FlockingModelKind::default(),
+            flocking_params: FlockingParams::default(),
+            handler: Handler::default(),
+            kennel: Kennel::default(),
+            mode: GameMode::FreePlay,
+            level: None,
+            replay: None,
+        }
+    }
+}
+
+impl HeadlessConfig {
+    /// Settings to play back a recorded run just as it was recorded, on the level it was played on.
+    /// Fails if that level can't be read from the assets folder.
+    pub fn from_replay(replay: Replay) -> Result<Self, String> {
+        let level = replay.level.as_deref().map(Level::load).transpose()?;
+        Ok(Self {
+            seed: replay.seed,
+            tick_rate: replay.tick_rate,
+            sheep_count: replay.sheep_count,
+            flocking_model: replay.flocking_params.model,
+            flocking_params: replay.flocking_params.clone(),
+            kennel: replay.kennel.clone(),
+            mode: replay.mode,
+            level,
+            replay: Some(replay),
+            ..Default::default()
+        })
+    }
+}
+
+/// Runs the sheep, dog, AI handler, field, level, obstacle, pen, trial, actions and replay plugins on `MinimalPlugins`,
+/// with no window, audio or GPU. Every call to [`HeadlessSimulation::run`] steps exactly the requested
+/// number of fixed ticks as fast as the machine allows, for tests and batch experiments.
+pub struct HeadlessSimulation {
+    app: App,
+}
+
+impl HeadlessSimulation {
+    pub fn new(config: HeadlessConfig) -> Self {
+        let mut app = App::new();
+
+        if let Some(level) = config.level {
+            app.insert_resource(CurrentLevel(level));
+        }
+        if let Some(replay) = config.replay {
+            app.insert_resource(replay);
+        }
+
+        app.insert_resource(GameSeed(config.seed))
+            .insert_resource(TickRate(config.tick_rate))
+            .insert_resource(SheepCount(config.sheep_count))
+            .insert_resource(config.flocking_model)
+            .insert_resource(config.flocking_params)
+            .insert_resource(config.handler)
+            .insert_resource(config.kennel)
+            .insert_resource(config.mode)
+            // Nobody is at the keyboard, and a player's saved bindings and whistles shouldn't be read
+            .insert_resource(InputMap::default())
+            .insert_resource(WhistleTemplates::default())
+            .insert_resource(Field::generated(
+                config.field_width,
+                config.field_height,
+                config.tile_size,
+                config.seed,
+            ))
+            .add_plugins((MinimalPlugins, AssetPlugin::default(), InputPlugin))
+            .add_state::<GameState>()
+            .add_plugins((
+                RngPlugin,
+                SimulationPlugin,
+                ActionsPlugin,
+                DogPlugin,
+                AiHandlerPlugin,
+                FlockingPlugin,
+                LevelPlugin,
+                SheepPlugin,
+                FieldPlugin,
+                ObstaclePlugin,
+                PenPlugin,
+                TrialPlugin,
+                ReplayPlugin,
+            ));
+
+        // Advance the clock by exactly one tick per update instead of following the wall clock
+        let period = app.world.resource::<FixedTime>().period;
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(period));
+
+        // There are no assets to wait for, so go straight to playing
+        app.world
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::Playing);
+
+        app.finish();
+        app.cleanup();
+
+        // The first update only starts the clock and spawns the flock, it never ticks
+        app.update();
+
+        Self { app }
+    }
+
+    pub fn run(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.app.update();
+        }
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.app.world.resource::<SimulationTick>().0
+    }
+
+    pub fn flock(&self) -> &Flock {
+        self.app.world.resource::<Flock>()
+    }
+
+    // Where every sheep is, in the order they were spawned
+    pub fn sheep_positions(&mut self) -> Vec<Vec2> {
+        self.app
+            .world
+            .query_filtered::<&Transform, With<Sheep>>()
+            .iter(&self.app.world)
+            .map(|transform| transform.translation.truncate())
+            .collect()
+    }
+
+    pub fn scorecard(&self) -> &Scorecard {
+        self.app.world.resource::<Scorecard>()
+    }
+
+    // Everything given to the dogs so far, to be played back later. Nothing is recorded while playing a replay back.
+    pub fn recording(&self) -> Option<&Replay> {
+        self.app.world.get_resource::<ReplayRecorder>().and_then(ReplayRecorder::replay)
+    }
+
+    pub fn world(&self) -> &World {
+        &self.app.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.app.world
+    }
+}
@@ -0,0 +1,359 @@
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+
+use crate::dog::HandlerPost;
+use crate::field::Field;
+use crate::loading::ConfigAssets;
+use crate::obstacle::ObstacleKind;
+use crate::rng::GameSeed;
+use crate::terrain::Terrain;
+use crate::trial::TrialCourse;
+use crate::GameState;
+
+// Where `cargo run` finds the assets, for levels read without the asset server
+const ASSETS_PATH: &str = "assets";
+// Tiled keeps flip flags in the top bits of every tile id
+const TILED_FLIP_FLAGS: u32 = 0xE000_0000;
+
+pub struct LevelPlugin;
+
+/// This plugin loads field layouts made in the Tiled map editor (`.tmj`, the JSON format).
+/// Once loading finishes the level sizes the field, places the handler's post and is
+/// kept as the [`CurrentLevel`] for the field, pen, dog and sheep to spawn from. Drive gates and a
+/// shedding ring drawn on the level lay out the [`TrialCourse`] too.
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Level>()
+            .init_asset_loader::<LevelLoader>()
+            .add_systems(OnExit(GameState::Loading), apply_level);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LevelObstacle {
+    pub kind: ObstacleKind,
+    pub points: Vec<Vec2>,
+    // Polygons are closed, polylines (e.g. a run of fence) are left open
+    pub closed: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct LevelPen {
+    pub polygon: Vec<Vec2>,
+    pub gates: Vec<Vec<Vec2>>,
+}
+
+// A field layout, with everything in world space centred on the middle of the map
+#[derive(TypeUuid, TypePath, Debug, Clone)]
+#[uuid = "c3f1d8a2-6b7e-4f0d-9e25-8a4b1c7d2e63"]
+pub struct Level {
+    // Where the level was loaded from, e.g. `levels/trial.tmj`, or empty for one made some other way
+    pub id: String,
+    pub width: f32,
+    pub height: f32,
+    pub tile_size: f32,
+    pub columns: usize,
+    pub rows: usize,
+    // One per tile, row by row from the top left, or none for ground generated from the game seed
+    pub terrain: Option<Vec<Terrain>>,
+    pub obstacles: Vec<LevelObstacle>,
+    pub pens: Vec<LevelPen>,
+    pub handler_post: Option<Vec2>,
+    pub dog_start: Option<Vec2>,
+    pub sheep_spawns: Vec<Rect>,
+    // Each drive gate's posts, in the order they are drawn on the map
+    pub drive_gates: Vec<(Vec2, Vec2)>,
+    // Centre and radius of the shedding ring
+    pub shed_ring: Option<(Vec2, f32)>,
+}
+
+// The level being played; without one the field falls back to plain grass
+#[derive(Resource, Debug, Clone)]
+pub struct CurrentLevel(pub Level);
+
+impl Level {
+    pub fn from_tiled_json(bytes: &[u8]) -> Result<Self, bevy::asset::Error> {
+        let map: TiledMap = serde_json::from_slice(bytes)?;
+        if map.tilewidth != map.tileheight {
+            return Err(bevy::asset::Error::msg("only square tiles are supported"));
+        }
+
+        let tile_size = map.tilewidth as f32;
+        let width = map.width as f32 * tile_size;
+        let height = map.height as f32 * tile_size;
+        // Tiled measures in pixels down from the top left corner
+        let to_world = |x: f32, y: f32| Vec2::new(x - width / 2., height / 2. - y);
+
+        let mut level = Level {
+            id: String::new(),
+            width,
+            height,
+            tile_size,
+            columns: map.width as usize,
+            rows: map.height as usize,
+            terrain: None,
+            obstacles: Vec::new(),
+            pens: Vec::new(),
+            handler_post: None,
+            dog_start: None,
+            sheep_spawns: Vec::new(),
+            drive_gates: Vec::new(),
+            shed_ring: None,
+        };
+        let mut gates = Vec::new();
+
+        for layer in &map.layers {
+            match layer {
+                TiledLayer::TileLayer { data } => {
+                    let terrain = level
+                        .terrain
+                        .get_or_insert_with(|| vec![Terrain::default(); map.width as usize * map.height as usize]);
+                    if data.len() != terrain.len() {
+                        return Err(bevy::asset::Error::msg("tile layers must cover the whole map"));
+                    }
+                    for (tile, gid) in terrain.iter_mut().zip(data) {
+                        // Empty tiles keep whatever the layers below put there
+                        if let Some(terrain) = map.terrain_for(*gid)? {
+                            *tile = terrain;
+                        }
+                    }
+                }
+                TiledLayer::ObjectGroup { objects } => {
+                    for object in objects {
+                        let outline: Vec<Vec2> = object
+                            .outline()
+                            .into_iter()
+                            .map(|(x, y)| to_world(x, y))
+                            .collect();
+                        let position = to_world(object.x, object.y);
+
+                        match object.class.as_str() {
+                            "pen" => level.pens.push(LevelPen {
+                                polygon: outline,
+                                gates: Vec::new(),
+                            }),
+                            "gate" => gates.push(outline),
+                            "handler_post" => level.handler_post = Some(position),
+                            "dog_start" => level.dog_start = Some(position),
+                            "sheep_spawn" => {
+                                let corner = to_world(object.x + object.width, object.y + object.height);
+                                level.sheep_spawns.push(Rect::from_corners(position, corner));
+                            }
+                            // Drawn as a line from post to post
+                            "drive_gate" => match (outline.first(), outline.last()) {
+                                (Some(a), Some(b)) if a != b => level.drive_gates.push((*a, *b)),
+                                _ => return Err(bevy::asset::Error::msg("a drive gate needs two posts")),
+                            },
+                            // Drawn as a circle, or anything else the ring fits inside
+                            "shed_ring" => {
+                                let centre = to_world(object.x + object.width / 2., object.y + object.height / 2.);
+                                level.shed_ring = Some((centre, object.width.max(object.height) / 2.));
+                            }
+                            class => match ObstacleKind::from_class(class) {
+                                Some(kind) => level.obstacles.push(LevelObstacle {
+                                    kind,
+                                    points: outline,
+                                    closed: object.polyline.is_none(),
+                                }),
+                                None => warn!("Ignoring map object {:?} of unknown class {:?}", object.name, class),
+                            },
+                        }
+                    }
+                }
+                TiledLayer::Other => {}
+            }
+        }
+
+        // Each gate belongs to whichever pen it sits closest to
+        for gate in gates {
+            let gate_centre = centroid(&gate);
+            let closest = level.pens.iter_mut().min_by(|a, b| {
+                let a = centroid(&a.polygon).distance(gate_centre);
+                let b = centroid(&b.polygon).distance(gate_centre);
+                a.total_cmp(&b)
+            });
+            match closest {
+                Some(pen) => pen.gates.push(gate),
+                None => return Err(bevy::asset::Error::msg("the map has a gate but no pen")),
+            }
+        }
+
+        Ok(level)
+    }
+
+    // Reads a level straight from the assets folder by its id, e.g. to play a replay back headless
+    pub fn load(id: &str) -> Result<Self, String> {
+        let path = std::path::Path::new(ASSETS_PATH).join(id);
+        let bytes = std::fs::read(&path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let mut level = Self::from_tiled_json(&bytes).map_err(|error| format!("{}: {}", path.display(), error))?;
+        level.id = id.to_string();
+        Ok(level)
+    }
+}
+
+fn centroid(polygon: &[Vec2]) -> Vec2 {
+    polygon.iter().copied().sum::<Vec2>() / polygon.len().max(1) as f32
+}
+
+// Just the parts of the Tiled JSON format a level is built from
+#[derive(Deserialize)]
+struct TiledMap {
+    width: u32,
+    height: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    layers: Vec<TiledLayer>,
+    #[serde(default)]
+    tilesets: Vec<TiledTileset>,
+}
+
+impl TiledMap {
+    fn terrain_for(&self, gid: u32) -> Result<Option<Terrain>, bevy::asset::Error> {
+        let gid = gid & !TILED_FLIP_FLAGS;
+        if gid == 0 {
+            return Ok(None);
+        }
+
+        // Tilesets are listed in order of their first id, the tile belongs to the last one starting at or before it
+        let tileset = self
+            .tilesets
+            .iter()
+            .filter(|tileset| tileset.firstgid <= gid)
+            .max_by_key(|tileset| tileset.firstgid)
+            .ok_or_else(|| bevy::asset::Error::msg(format!("tile {} is not in any tileset", gid)))?;
+        if let Some(source) = &tileset.source {
+            return Err(bevy::asset::Error::msg(format!(
+                "external tileset {} is not supported, embed it in the map",
+                source
+            )));
+        }
+
+        let id = gid - tileset.firstgid;
+        let class = tileset
+            .tiles
+            .iter()
+            .find(|tile| tile.id == id)
+            .map(|tile| tile.class.as_str())
+            .unwrap_or_default();
+        Terrain::from_class(class)
+            .map(Some)
+            .ok_or_else(|| bevy::asset::Error::msg(format!("tile {} has unknown terrain class {:?}", gid, class)))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum TiledLayer {
+    #[serde(rename = "tilelayer")]
+    TileLayer { data: Vec<u32> },
+    #[serde(rename = "objectgroup")]
+    ObjectGroup { objects: Vec<TiledObject> },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct TiledTileset {
+    firstgid: u32,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    tiles: Vec<TiledTile>,
+}
+
+#[derive(Deserialize)]
+struct TiledTile {
+    id: u32,
+    // Tiled 1.9 briefly wrote this as "class"
+    #[serde(default, rename = "type", alias = "class")]
+    class: String,
+}
+
+#[derive(Deserialize)]
+struct TiledObject {
+    #[serde(default)]
+    name: String,
+    #[serde(default, rename = "type", alias = "class")]
+    class: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    polygon: Option<Vec<TiledPoint>>,
+    #[serde(default)]
+    polyline: Option<Vec<TiledPoint>>,
+}
+
+#[derive(Deserialize)]
+struct TiledPoint {
+    x: f32,
+    y: f32,
+}
+
+impl TiledObject {
+    // The object's shape in map pixels, polygons and polylines as drawn and everything else as its bounding rectangle
+    fn outline(&self) -> Vec<(f32, f32)> {
+        match self.polygon.as_ref().or(self.polyline.as_ref()) {
+            Some(points) => points.iter().map(|point| (self.x + point.x, self.y + point.y)).collect(),
+            None => vec![
+                (self.x, self.y),
+                (self.x + self.width, self.y),
+                (self.x + self.width, self.y + self.height),
+                (self.x, self.y + self.height),
+            ],
+        }
+    }
+}
+
+#[derive(Default)]
+struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let mut level = Level::from_tiled_json(bytes)?;
+            level.id = load_context.path().to_string_lossy().replace('\\', "/");
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tmj"]
+    }
+}
+
+// Makes the loaded level current, or keeps one inserted up front (e.g. by the headless simulation),
+// and sizes the field and places the handler's post from it
+fn apply_level(
+    mut commands: Commands,
+    config: Option<Res<ConfigAssets>>,
+    levels: Res<Assets<Level>>,
+    current: Option<Res<CurrentLevel>>,
+    seed: Res<GameSeed>,
+) {
+    let loaded = config.and_then(|config| levels.get(&config.level).cloned());
+    let Some(level) = loaded.or_else(|| current.map(|current| current.0.clone())) else {
+        return;
+    };
+
+    info!("Playing a {}x{} level", level.width, level.height);
+    commands.insert_resource(Field::from_level(&level, seed.0));
+    if let Some(course) = TrialCourse::from_level(&level) {
+        commands.insert_resource(course);
+    }
+    if let Some(handler_post) = level.handler_post {
+        commands.insert_resource(HandlerPost(handler_post));
+    }
+    commands.insert_resource(CurrentLevel(level));
+}
@@ -6,22 +6,76 @@ use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
 use bevy::prelude::*;
 
 use crate::actions::ActionsPlugin;
+use crate::ai_handler::AiHandlerPlugin;
 use crate::audio::InternalAudioPlugin;
+use crate::camera::CameraPlugin;
+use crate::controls::ControlsPlugin;
 use crate::dog::DogPlugin;
+use crate::dog_panel::DogPanelPlugin;
+use crate::dog_target::DogTargetPlugin;
 use crate::field::FieldPlugin;
+use crate::flocking::FlockingPlugin;
+use crate::level::LevelPlugin;
 use crate::loading::LoadingPlugin;
 use crate::menu::MenuPlugin;
-use crate::player::PlayerPlugin;
+use crate::obstacle::ObstaclePlugin;
+use crate::pen::PenPlugin;
+use crate::replay::ReplayPlugin;
+use crate::results::ResultsPlugin;
+use crate::rng::RngPlugin;
 use crate::sheep::SheepPlugin;
+use crate::simulation::SimulationPlugin;
+use crate::tilemap::TilemapPlugin;
+use crate::trial::TrialPlugin;
 
 mod actions;
+mod ai_handler;
 mod audio;
+mod camera;
+mod controls;
+mod level;
 mod loading;
 mod menu;
-mod player;
+mod obstacle;
+mod pen;
+mod replay;
+mod results;
+mod rng;
 mod field;
+mod flocking;
+mod flocking_model;
+mod headless;
 mod dog;
+mod dog_panel;
+mod dog_target;
 mod sheep;
+mod simulation;
+mod spatial_hash;
+mod terrain;
+mod tilemap;
+mod trial;
+
+pub use crate::actions::{
+    read_wav, recognise_recording, recognise_wav, AudioInput, Binding, CommandKeys, GameControl, GestureRecogniser,
+    InputMap, WhistleNote, WhistleRecogniser, WhistleTemplate, WhistleTemplates,
+};
+pub use crate::ai_handler::{AiHandler, Handler};
+pub use crate::camera::{CameraMode, GameCamera};
+pub use crate::dog::{CallStyle, DogCommand, DogOrder, DogProfile, Kennel, SelectedDog};
+pub use crate::dog_target::DogTarget;
+pub use crate::field::Field;
+pub use crate::flocking::FlockingParams;
+pub use crate::flocking_model::{FlockingModel, FlockingModelKind, Neighbour, Neighbourhood};
+pub use crate::headless::{HeadlessConfig, HeadlessSimulation};
+pub use crate::level::{CurrentLevel, Level, LevelObstacle, LevelPen};
+pub use crate::obstacle::{resolve_movement, Obstacle, ObstacleKind};
+pub use crate::pen::{Gate, GateCommand, Pen, PenProgress, RunComplete, SheepEscaped, SheepPenned};
+pub use crate::replay::{Replay, ReplayEntry, ReplayInput, REPLAY_VERSION};
+pub use crate::rng::GameSeed;
+pub use crate::sheep::SheepCount;
+pub use crate::simulation::TickRate;
+pub use crate::terrain::{generate_terrain, Terrain};
+pub use crate::trial::{Fault, GameMode, PhaseScore, Scorecard, TrialCourse, TrialPhase};
 
 // This example game uses States to separate logic
 // See https://bevy-cheatbook.github.io/programming/states.html
@@ -35,22 +89,46 @@ enum GameState {
     Playing,
     // Here the menu is drawn and waiting for player interaction
     Menu,
+    // The key bindings are listed and can be changed
+    Controls,
+    // The trial is over and its scorecard is shown
+    Results,
 }
 
+// Marks everything spawned for a run, so it can all be cleared away before the next one starts
+#[derive(Component)]
+struct RunEntity;
+
 pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app.add_state::<GameState>()
             .add_plugins((
+                RngPlugin,
+                SimulationPlugin,
                 LoadingPlugin,
+                LevelPlugin,
                 MenuPlugin,
+                ControlsPlugin,
                 ActionsPlugin,
                 InternalAudioPlugin,
-                // PlayerPlugin,
-                // DogPlugin,
+                CameraPlugin,
+            ))
+            .add_plugins((
+                DogPlugin,
+                AiHandlerPlugin,
+                DogPanelPlugin,
+                DogTargetPlugin,
+                FlockingPlugin,
                 SheepPlugin,
                 FieldPlugin,
+                TilemapPlugin,
+                ObstaclePlugin,
+                PenPlugin,
+                TrialPlugin,
+                ResultsPlugin,
+                ReplayPlugin,
             ));
 
         #[cfg(debug_assertions)]
@@ -1,3 +1,5 @@
+use crate::flocking::FlockingParams;
+use crate::level::Level;
 use crate::GameState;
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::*;
@@ -15,7 +17,8 @@ impl Plugin for LoadingPlugin {
         )
         .add_collection_to_loading_state::<_, FontAssets>(GameState::Loading)
         .add_collection_to_loading_state::<_, AudioAssets>(GameState::Loading)
-        .add_collection_to_loading_state::<_, TextureAssets>(GameState::Loading);
+        .add_collection_to_loading_state::<_, TextureAssets>(GameState::Loading)
+        .add_collection_to_loading_state::<_, ConfigAssets>(GameState::Loading);
     }
 }
 
@@ -43,5 +46,23 @@ pub struct TextureAssets {
     #[asset(path = "textures/tile_grass_1.png")]
     pub texture_tile_grass_1: Handle<Image>,
     #[asset(path = "textures/tile_grass_2.png")]
-    pub texture_tile_grass_2: Handle<Image>
+    pub texture_tile_grass_2: Handle<Image>,
+    #[asset(path = "textures/tile_long_grass.png")]
+    pub texture_tile_long_grass: Handle<Image>,
+    #[asset(path = "textures/tile_mud.png")]
+    pub texture_tile_mud: Handle<Image>,
+    #[asset(path = "textures/tile_water.png")]
+    pub texture_tile_water: Handle<Image>,
+    #[asset(path = "textures/tile_rock.png")]
+    pub texture_tile_rock: Handle<Image>,
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct ConfigAssets {
+    // Never read, holding the handle keeps `sheep.flocking.ron` loaded so its edits are picked up
+    #[allow(dead_code)]
+    #[asset(path = "sheep.flocking.ron")]
+    pub flocking: Handle<FlockingParams>,
+    #[asset(path = "levels/trial.tmj")]
+    pub level: Handle<Level>,
 }
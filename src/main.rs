@@ -1,17 +1,98 @@
 // disable console on windows for release builds
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use bevy::asset::ChangeWatcher;
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
 use bevy::winit::WinitWindows;
 use bevy::DefaultPlugins;
-use rusty_sheep::GamePlugin;
+use rusty_sheep::{
+    FlockingModelKind, GamePlugin, GameSeed, HeadlessConfig, HeadlessSimulation, Replay, SheepCount, TickRate,
+};
 use std::io::Cursor;
+use std::time::Duration;
 use winit::window::Icon;
 
 fn main() {
-    App::new()
-        .insert_resource(Msaa::Off)
+    // Pin the seed to reproduce a previous run, e.g. `RUSTY_SHEEP_SEED=42 cargo run`
+    let seed: Option<u64> = std::env::var("RUSTY_SHEEP_SEED").ok().and_then(|seed| seed.parse().ok());
+
+    // `cargo run -- --replay replay.ron` plays back a run saved by an earlier game
+    let args: Vec<String> = std::env::args().collect();
+    let replay = match args.iter().position(|arg| arg == "--replay") {
+        Some(position) => {
+            let path = args.get(position + 1).map(String::as_str).unwrap_or("replay.ron");
+            match Replay::load(path) {
+                Ok(replay) => Some(replay),
+                Err(error) => {
+                    eprintln!("Couldn't load the replay {}", error);
+                    return;
+                }
+            }
+        }
+        None => None,
+    };
+
+    // `cargo run -- --flocking couzin` overrides the model in `sheep.flocking.ron`, a replay keeps the one it was recorded with
+    let flocking_model = match args.iter().position(|arg| arg == "--flocking") {
+        Some(position) => {
+            let name = args.get(position + 1).map(String::as_str).unwrap_or_default();
+            match FlockingModelKind::from_name(name) {
+                Some(kind) => Some(kind),
+                None => {
+                    let names: Vec<&str> = FlockingModelKind::ALL.iter().map(|kind| kind.name()).collect();
+                    eprintln!("Unknown flocking model {:?}, pick one of {}", name, names.join(", "));
+                    return;
+                }
+            }
+        }
+        None => None,
+    };
+
+    // `cargo run -- --headless 10000` simulates that many ticks without a window and exits
+    if let Some(position) = args.iter().position(|arg| arg == "--headless") {
+        let ticks = args.get(position + 1).and_then(|ticks| ticks.parse().ok()).unwrap_or(600);
+        let config = match replay {
+            Some(replay) => match HeadlessConfig::from_replay(replay) {
+                Ok(config) => config,
+                Err(error) => {
+                    eprintln!("Couldn't load the replay's level {}", error);
+                    return;
+                }
+            },
+            None => HeadlessConfig {
+                seed: seed.unwrap_or_default(),
+                flocking_model: flocking_model.unwrap_or_default(),
+                ..default()
+            },
+        };
+        run_headless(config, ticks);
+        return;
+    }
+
+    let mut app = App::new();
+
+    match replay {
+        Some(replay) => {
+            // Pinned for the whole run, so neither the command line nor editing `sheep.flocking.ron` can change them
+            app.insert_resource(GameSeed(replay.seed))
+                .insert_resource(TickRate(replay.tick_rate))
+                .insert_resource(SheepCount(replay.sheep_count))
+                .insert_resource(replay.flocking_params.model)
+                .insert_resource(replay.flocking_params.clone())
+                .insert_resource(replay);
+        }
+        None => {
+            if let Some(seed) = seed {
+                app.insert_resource(GameSeed(seed));
+            }
+            if let Some(flocking_model) = flocking_model {
+                app.insert_resource(flocking_model);
+            }
+        }
+    }
+
+    app.insert_resource(Msaa::Off)
         .insert_resource(ClearColor(Color::rgb(0.0, 0.4, 0.0)))
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
@@ -25,12 +106,33 @@ fn main() {
                 ..default()
             }),
             ..default()
+        }).set(AssetPlugin {
+            // Reload assets such as `sheep.flocking.ron` when they change on disk
+            watch_for_changes: ChangeWatcher::with_delay(Duration::from_millis(200)),
+            ..default()
         }))
         .add_plugins(GamePlugin)
         .add_systems(Startup, set_window_icon)
         .run();
 }
 
+fn run_headless(config: HeadlessConfig, ticks: u64) {
+    let seed = config.seed;
+    let mut simulation = HeadlessSimulation::new(config);
+    simulation.run(ticks);
+
+    let flock = simulation.flock();
+    println!(
+        "seed {} after {} ticks: {} sheep, centre ({:.1}, {:.1}), radius {:.1}",
+        seed,
+        simulation.tick(),
+        flock.count,
+        flock.centre.x,
+        flock.centre.y,
+        flock.radius
+    );
+}
+
 // Sets the icon on windows and X11
 fn set_window_icon(
     windows: NonSend<WinitWindows>,
@@ -1,10 +1,14 @@
+use crate::ai_handler::Handler;
+use crate::dog::Kennel;
 use crate::loading::FontAssets;
+use crate::trial::GameMode;
 use crate::GameState;
+use bevy::ecs::system::EntityCommands;
 use bevy::prelude::*;
 
 pub struct MenuPlugin;
 
-/// This plugin is responsible for the game menu (containing only one button...)
+/// This plugin is responsible for the game menu (buttons for free play, a trial, working a brace of dogs, watching the computer play, and the controls)
 /// The menu is only drawn during the State `GameState::Menu` and is removed when that state is exited
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
@@ -30,50 +34,107 @@ impl Default for ButtonColors {
     }
 }
 
+// The root of the menu, so it can all be removed at once
+#[derive(Component)]
+struct Menu;
+
+// Which mode a menu button starts, which dogs are out and who handles them
+#[derive(Component)]
+struct PlayButton {
+    mode: GameMode,
+    handler: Handler,
+    kennel: Kennel,
+}
+
+// Opens the screen for changing the key bindings
+#[derive(Component)]
+struct ControlsButton;
+
 fn setup_menu(
     mut commands: Commands,
     font_assets: Res<FontAssets>,
     button_colors: Res<ButtonColors>,
 ) {
-    commands.spawn(Camera2dBundle::default());
     commands
-        .spawn(ButtonBundle {
+        .spawn(NodeBundle {
             style: Style {
-                width: Val::Px(120.0),
-                height: Val::Px(50.0),
                 margin: UiRect::all(Val::Auto),
-                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::Column,
                 align_items: AlignItems::Center,
                 ..Default::default()
             },
-            background_color: button_colors.normal.into(),
             ..Default::default()
         })
+        .insert(Menu)
         .with_children(|parent| {
-            parent.spawn(TextBundle::from_section(
-                "Play",
-                TextStyle {
-                    font: font_assets.fira_sans.clone(),
-                    font_size: 40.0,
-                    color: Color::rgb(0.9, 0.9, 0.9),
-                },
-            ));
+            let buttons = [
+                ("Play", GameMode::FreePlay, Handler::Player, Kennel::default()),
+                ("Trial", GameMode::Trial, Handler::Player, Kennel::default()),
+                ("Brace", GameMode::FreePlay, Handler::Player, Kennel::brace()),
+                // The computer runs the dogs, for demos and to show how it's done
+                ("Watch", GameMode::FreePlay, Handler::Ai, Kennel::default()),
+            ];
+            for (label, mode, handler, kennel) in buttons {
+                spawn_button(parent, label, &font_assets, &button_colors).insert(PlayButton { mode, handler, kennel });
+            }
+            spawn_button(parent, "Controls", &font_assets, &button_colors).insert(ControlsButton);
         });
 }
 
+fn spawn_button<'w, 's, 'a>(
+    parent: &'a mut ChildBuilder<'w, 's, '_>,
+    label: &str,
+    font_assets: &FontAssets,
+    button_colors: &ButtonColors,
+) -> EntityCommands<'w, 's, 'a> {
+    let mut button = parent.spawn(ButtonBundle {
+        style: Style {
+            width: Val::Px(160.0),
+            height: Val::Px(50.0),
+            margin: UiRect::all(Val::Px(10.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..Default::default()
+        },
+        background_color: button_colors.normal.into(),
+        ..Default::default()
+    });
+    button.with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            label,
+            TextStyle {
+                font: font_assets.fira_sans.clone(),
+                font_size: 40.0,
+                color: Color::rgb(0.9, 0.9, 0.9),
+            },
+        ));
+    });
+    button
+}
+
 fn click_play_button(
     button_colors: Res<ButtonColors>,
     mut state: ResMut<NextState<GameState>>,
+    mut game_mode: ResMut<GameMode>,
+    mut handler: ResMut<Handler>,
+    mut kennel: ResMut<Kennel>,
     mut interaction_query: Query<
-        (&Interaction, &mut BackgroundColor),
+        (&Interaction, &mut BackgroundColor, Option<&PlayButton>),
         (Changed<Interaction>, With<Button>),
     >,
 ) {
-    for (interaction, mut color) in &mut interaction_query {
+    for (interaction, mut color, button) in &mut interaction_query {
         match *interaction {
-            Interaction::Pressed => {
-                state.set(GameState::Playing);
-            }
+            Interaction::Pressed => match button {
+                Some(button) => {
+                    *game_mode = button.mode;
+                    *handler = button.handler;
+                    *kennel = button.kennel.clone();
+                    state.set(GameState::Playing);
+                }
+                // The only other button is the controls one
+                None => state.set(GameState::Controls),
+            },
             Interaction::Hovered => {
                 *color = button_colors.hovered.into();
             }
@@ -84,6 +145,6 @@ fn click_play_button(
     }
 }
 
-fn cleanup_menu(mut commands: Commands, button: Query<Entity, With<Button>>) {
-    commands.entity(button.single()).despawn_recursive();
+fn cleanup_menu(mut commands: Commands, menu: Query<Entity, With<Menu>>) {
+    commands.entity(menu.single()).despawn_recursive();
 }
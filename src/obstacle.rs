@@ -0,0 +1,262 @@
+use bevy::prelude::*;
+
+use crate::field::Field;
+use crate::level::CurrentLevel;
+use crate::pen::{polygon_contains, spawn_polygon_outline, spawn_polyline};
+use crate::{GameState, RunEntity};
+
+const OBSTACLE_COLOR: Color = Color::rgb(0.35, 0.3, 0.25);
+const BOUNDARY_COLOR: Color = Color::rgb(0.45, 0.35, 0.2);
+
+pub struct ObstaclePlugin;
+
+/// This plugin places the level's fences, hurdles, trees and rocks, and a fence around the
+/// edge of the field, as [`Obstacle`]s. Sheep and dog movement is resolved against them with
+/// [`resolve_movement`], so nothing can pass through.
+impl Plugin for ObstaclePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Playing), spawn_obstacles);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObstacleKind {
+    Fence,
+    Hurdle,
+    Tree,
+    Rock,
+}
+
+impl ObstacleKind {
+    // The class given to objects of this kind in a Tiled map
+    pub fn from_class(class: &str) -> Option<Self> {
+        match class {
+            "fence" => Some(ObstacleKind::Fence),
+            "hurdle" => Some(ObstacleKind::Hurdle),
+            "tree" => Some(ObstacleKind::Tree),
+            "rock" => Some(ObstacleKind::Rock),
+            _ => None,
+        }
+    }
+
+    // How far the obstacle's edges stand out either side of its outline
+    pub fn half_width(self) -> f32 {
+        match self {
+            ObstacleKind::Fence => 3.0,
+            ObstacleKind::Hurdle => 5.0,
+            ObstacleKind::Tree | ObstacleKind::Rock => 0.0,
+        }
+    }
+
+    // Fences and hurdles are only their edges, trees and rocks are solid all the way through
+    pub fn is_solid(self) -> bool {
+        matches!(self, ObstacleKind::Tree | ObstacleKind::Rock)
+    }
+}
+
+// Something fixed in the field, as a line of points in world space
+#[derive(Component, Debug, Clone)]
+pub struct Obstacle {
+    pub kind: ObstacleKind,
+    pub points: Vec<Vec2>,
+    // Whether the last point joins back up with the first
+    pub closed: bool,
+}
+
+impl Obstacle {
+    pub fn edges(&self) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+        let closing = match (self.closed, self.points.first(), self.points.last()) {
+            (true, Some(first), Some(last)) if self.points.len() > 2 => Some((*last, *first)),
+            _ => None,
+        };
+        self.points.windows(2).map(|pair| (pair[0], pair[1])).chain(closing)
+    }
+
+    // The nearest point on the obstacle's outline
+    pub fn closest_point(&self, point: Vec2) -> Option<Vec2> {
+        if let [only] = self.points.as_slice() {
+            return Some(*only);
+        }
+        self.edges()
+            .map(|(a, b)| closest_point_on_segment(a, b, point))
+            .min_by(|a, b| a.distance_squared(point).total_cmp(&b.distance_squared(point)))
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.kind.is_solid() && self.closed && polygon_contains(&self.points, point)
+    }
+
+    // How far a point is from the surface of the obstacle, negative once inside it,
+    // and which way is out from there
+    pub fn clearance(&self, point: Vec2) -> Option<(f32, Vec2)> {
+        let closest = self.closest_point(point)?;
+        let offset = point - closest;
+        if self.contains(point) {
+            return Some((-offset.length(), -offset.normalize_or_zero()));
+        }
+        Some((offset.length() - self.kind.half_width(), offset.normalize_or_zero()))
+    }
+
+    fn crosses(&self, from: Vec2, to: Vec2) -> bool {
+        self.edges().any(|(a, b)| segments_cross(from, to, a, b))
+    }
+}
+
+fn closest_point_on_segment(a: Vec2, b: Vec2, point: Vec2) -> Vec2 {
+    let edge = b - a;
+    let t = (point - a).dot(edge) / edge.length_squared().max(f32::EPSILON);
+    a + edge * t.clamp(0.0, 1.0)
+}
+
+// Whether the segments a-b and c-d cross each other, touching doesn't count
+fn segments_cross(a: Vec2, b: Vec2, c: Vec2, d: Vec2) -> bool {
+    let side = |from: Vec2, to: Vec2, point: Vec2| (to - from).perp_dot(point - from);
+    side(c, d, a) * side(c, d, b) < 0.0 && side(a, b, c) * side(a, b, d) < 0.0
+}
+
+// Where a round body moving from `from` towards `to` ends up. Steps across an obstacle's edge
+// slide along it instead, and the body is then pushed back out of anything it overlaps.
+pub fn resolve_movement(obstacles: &[&Obstacle], from: Vec2, to: Vec2, radius: f32) -> Vec2 {
+    let mut to = to;
+
+    for obstacle in obstacles {
+        for (a, b) in obstacle.edges() {
+            if segments_cross(from, to, a, b) {
+                let along = (b - a).normalize_or_zero();
+                to = from + along * (to - from).dot(along);
+            }
+        }
+    }
+    // Sliding along one edge could still take it through another, better to stay put
+    if obstacles.iter().any(|obstacle| obstacle.crosses(from, to)) {
+        to = from;
+    }
+
+    for obstacle in obstacles {
+        let Some((distance, out)) = obstacle.clearance(to) else {
+            continue;
+        };
+        if distance < radius {
+            // Standing right on the outline there's no telling which side is out, so go back
+            let out = if out == Vec2::ZERO { (from - to).normalize_or_zero() } else { out };
+            to += out * (radius - distance);
+        }
+    }
+    // Being pushed out of one obstacle can shove it through another close by
+    if obstacles.iter().any(|obstacle| obstacle.crosses(from, to)) {
+        to = from;
+    }
+
+    to
+}
+
+fn spawn_obstacle(commands: &mut Commands, obstacle: Obstacle, color: Color) {
+    let entity = if obstacle.closed {
+        spawn_polygon_outline(commands, &obstacle.points, color)
+    } else {
+        spawn_polyline(commands, &obstacle.points, color)
+    };
+    commands.entity(entity).insert((obstacle, RunEntity));
+}
+
+fn spawn_obstacles(mut commands: Commands, field: Res<Field>, level: Option<Res<CurrentLevel>>) {
+    // A fence all the way round, so nothing can leave the field
+    let (x, y) = (field.width / 2., field.height / 2.);
+    let boundary = Obstacle {
+        kind: ObstacleKind::Fence,
+        points: vec![Vec2::new(-x, -y), Vec2::new(x, -y), Vec2::new(x, y), Vec2::new(-x, y)],
+        closed: true,
+    };
+    spawn_obstacle(&mut commands, boundary, BOUNDARY_COLOR);
+
+    let Some(level) = level else {
+        return;
+    };
+    for obstacle in &level.0.obstacles {
+        let obstacle = Obstacle {
+            kind: obstacle.kind,
+            points: obstacle.points.clone(),
+            closed: obstacle.closed,
+        };
+        spawn_obstacle(&mut commands, obstacle, OBSTACLE_COLOR);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pushing_out_never_crosses_a_fence() {
+        // A fence with a tree just the other side of where the body stands
+        let fence = Obstacle {
+            kind: ObstacleKind::Fence,
+            points: vec![Vec2::new(0., -100.), Vec2::new(0., 100.)],
+            closed: false,
+        };
+        let tree = Obstacle {
+            kind: ObstacleKind::Tree,
+            points: vec![Vec2::new(-30., -10.), Vec2::new(-10., -10.), Vec2::new(-10., 10.), Vec2::new(-30., 10.)],
+            closed: true,
+        };
+
+        let from = Vec2::new(-4., 0.);
+        let resolved = resolve_movement(&[&tree, &fence], from, Vec2::new(-3., 0.), 12.);
+        assert!(resolved.x < 0., "pushed through the fence to {}", resolved);
+        // Without anywhere to go it stays where it was
+        assert_eq!(resolved, from);
+    }
+
+    fn fence(points: &[Vec2]) -> Obstacle {
+        Obstacle {
+            kind: ObstacleKind::Fence,
+            points: points.to_vec(),
+            closed: false,
+        }
+    }
+
+    #[test]
+    fn touching_isnt_crossing() {
+        let (a, b) = (Vec2::new(0., -10.), Vec2::new(0., 10.));
+
+        assert!(segments_cross(Vec2::new(-5., 0.), Vec2::new(5., 0.), a, b));
+        // Ending on the line, starting from it, or meeting it end to end
+        assert!(!segments_cross(Vec2::new(-5., 0.), Vec2::ZERO, a, b));
+        assert!(!segments_cross(Vec2::ZERO, Vec2::new(5., 0.), a, b));
+        assert!(!segments_cross(Vec2::new(-5., 10.), b, a, b));
+        // Running alongside or along it
+        assert!(!segments_cross(Vec2::new(1., -10.), Vec2::new(1., 10.), a, b));
+        assert!(!segments_cross(Vec2::new(0., -5.), Vec2::new(0., 5.), a, b));
+    }
+
+    #[test]
+    fn moving_into_a_fence_slides_along_it() {
+        let fence = fence(&[Vec2::new(0., -100.), Vec2::new(0., 100.)]);
+
+        let resolved = resolve_movement(&[&fence], Vec2::new(-20., 0.), Vec2::new(20., 10.), 5.);
+        assert_eq!(resolved, Vec2::new(-20., 10.));
+        // Moving clear of it isn't touched
+        assert_eq!(resolve_movement(&[&fence], Vec2::new(-20., 0.), Vec2::new(-10., 10.), 5.), Vec2::new(-10., 10.));
+    }
+
+    #[test]
+    fn moving_into_a_corner_stays_put() {
+        let upright = fence(&[Vec2::new(0., -100.), Vec2::new(0., 100.)]);
+        let across = fence(&[Vec2::new(-100., 0.), Vec2::ZERO]);
+
+        let from = Vec2::new(-20., -20.);
+        assert_eq!(resolve_movement(&[&upright, &across], from, Vec2::new(20., 30.), 5.), from);
+    }
+
+    #[test]
+    fn bodies_are_pushed_out_to_their_radius() {
+        let rock = Obstacle {
+            kind: ObstacleKind::Rock,
+            points: vec![Vec2::new(-10., -10.), Vec2::new(10., -10.), Vec2::new(10., 10.), Vec2::new(-10., 10.)],
+            closed: true,
+        };
+
+        let resolved = resolve_movement(&[&rock], Vec2::new(30., 0.), Vec2::new(15., 0.), 8.);
+        assert_eq!(resolved, Vec2::new(18., 0.));
+    }
+}
@@ -0,0 +1,411 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::level::CurrentLevel;
+use crate::obstacle::{Obstacle, ObstacleKind};
+use crate::sheep::Sheep;
+use crate::simulation::SimulationSet;
+use crate::{GameState, RunEntity};
+
+const PEN_COLOR: Color = Color::rgb(0.55, 0.4, 0.25);
+const GATE_COLOR: Color = Color::rgb(0.8, 0.65, 0.3);
+const FENCE_THICKNESS: f32 = 6.0;
+
+pub struct PenPlugin;
+
+/// This plugin handles the pen the sheep have to be brought into.
+/// The pen's fence is an [`Obstacle`] all round but for the gateway, which a shut gate fills.
+/// Every tick it works out which sheep are inside a pen and reports sheep coming and going,
+/// and the run is complete once every sheep is penned with the gates shut.
+impl Plugin for PenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SheepPenned>()
+            .add_event::<SheepEscaped>()
+            .add_event::<GateCommand>()
+            .add_event::<RunComplete>()
+            .init_resource::<PenProgress>()
+            .add_systems(OnEnter(GameState::Playing), spawn_pen)
+            .add_systems(Update, operate_gates.run_if(in_state(GameState::Playing)))
+            .add_systems(
+                FixedUpdate,
+                detect_penned_sheep
+                    .in_set(SimulationSet::Evaluate)
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+// An enclosure, as a polygon in world space
+#[derive(Component, Debug)]
+pub struct Pen {
+    pub polygon: Vec<Vec2>,
+}
+
+impl Pen {
+    // Even-odd ray cast, so concave pens work too
+    pub fn contains(&self, point: Vec2) -> bool {
+        polygon_contains(&self.polygon, point)
+    }
+}
+
+// The gap in a pen's fence, as a polygon in world space. The gate entity also has an
+// `Obstacle`, which is only fenced while the gate is shut.
+#[derive(Component, Debug)]
+pub struct Gate {
+    pub pen: Entity,
+    pub polygon: Vec<Vec2>,
+    pub open: bool,
+}
+
+impl Gate {
+    // Opens or shuts the gate, fencing off the gateway or clearing it, and only drawing it while shut
+    pub fn swing(&mut self, open: bool, obstacle: &mut Obstacle, visibility: &mut Visibility) {
+        self.open = open;
+        obstacle.points = if open { Vec::new() } else { self.polygon.clone() };
+        // An open gate is swung out of the way, so it isn't drawn
+        *visibility = if open { Visibility::Hidden } else { Visibility::Inherited };
+    }
+
+    pub fn obey(&mut self, command: GateCommand, obstacle: &mut Obstacle, visibility: &mut Visibility) {
+        let open = match command {
+            GateCommand::Open => true,
+            GateCommand::Close => false,
+            GateCommand::Toggle => !self.open,
+        };
+        self.swing(open, obstacle, visibility);
+    }
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct SheepPenned {
+    pub sheep: Entity,
+    pub pen: Entity,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct SheepEscaped {
+    pub sheep: Entity,
+    pub pen: Entity,
+}
+
+// Sent to open or shut every gate
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GateCommand {
+    Open,
+    Close,
+    Toggle,
+}
+
+// Sent once, the first time every sheep is penned with the gates shut
+#[derive(Event, Debug, Clone, Copy)]
+pub struct RunComplete;
+
+#[derive(Resource, Default, Debug)]
+pub struct PenProgress {
+    // Which pen each penned sheep is in
+    penned: HashMap<Entity, Entity>,
+    // How many times a penned sheep has got back out
+    pub escapes: usize,
+    pub complete: bool,
+}
+
+impl PenProgress {
+    pub fn penned_count(&self) -> usize {
+        self.penned.len()
+    }
+
+    pub fn is_penned(&self, sheep: Entity) -> bool {
+        self.penned.contains_key(&sheep)
+    }
+}
+
+pub fn polygon_contains(polygon: &[Vec2], point: Vec2) -> bool {
+    let mut inside = false;
+    let mut j = polygon.len().wrapping_sub(1);
+
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[i], polygon[j]);
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < (b.x - a.x) * (point.y - a.y) / (b.y - a.y) + a.x
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+
+    inside
+}
+
+// A thin sprite lying along the edge from `a` to `b`
+fn fence_sprite(a: Vec2, b: Vec2, color: Color) -> SpriteBundle {
+    let edge = b - a;
+    let midpoint = (a + b) / 2.0;
+
+    SpriteBundle {
+        sprite: Sprite {
+            color,
+            custom_size: Some(Vec2::new(edge.length(), FENCE_THICKNESS)),
+            ..Default::default()
+        },
+        transform: Transform::from_translation(midpoint.extend(0.5))
+            .with_rotation(Quat::from_rotation_z(edge.y.atan2(edge.x))),
+        ..Default::default()
+    }
+}
+
+pub fn spawn_polygon_outline(commands: &mut Commands, polygon: &[Vec2], color: Color) -> Entity {
+    let closed: Vec<Vec2> = polygon.iter().chain(polygon.first()).copied().collect();
+    spawn_polyline(commands, &closed, color)
+}
+
+// Fencing along each pair of consecutive points, without joining the last back to the first
+pub fn spawn_polyline(commands: &mut Commands, points: &[Vec2], color: Color) -> Entity {
+    commands
+        .spawn(SpatialBundle::default())
+        .with_children(|parent| {
+            for pair in points.windows(2) {
+                parent.spawn(fence_sprite(pair[0], pair[1], color));
+            }
+        })
+        .id()
+}
+
+// Spawns the level's pens, or without a level a square pen in the top right corner of the
+// play area with its gate in the middle of the bottom fence
+fn spawn_pen(mut commands: Commands, level: Option<Res<CurrentLevel>>) {
+    // Nothing is penned yet in a new run
+    commands.insert_resource(PenProgress::default());
+
+    if let Some(level) = level {
+        for pen in &level.0.pens {
+            spawn_pen_with_gates(&mut commands, pen.polygon.clone(), &pen.gates);
+        }
+        return;
+    }
+
+    let centre = Vec2::new(600., 600.);
+    let half_size = 120.;
+    let gate_half_width = 40.;
+
+    let polygon = vec![
+        centre + Vec2::new(-half_size, -half_size),
+        centre + Vec2::new(half_size, -half_size),
+        centre + Vec2::new(half_size, half_size),
+        centre + Vec2::new(-half_size, half_size),
+    ];
+    let gate_polygon = vec![
+        centre + Vec2::new(-gate_half_width, -half_size - FENCE_THICKNESS),
+        centre + Vec2::new(gate_half_width, -half_size - FENCE_THICKNESS),
+        centre + Vec2::new(gate_half_width, -half_size + FENCE_THICKNESS),
+        centre + Vec2::new(-gate_half_width, -half_size + FENCE_THICKNESS),
+    ];
+
+    spawn_pen_with_gates(&mut commands, polygon, &[gate_polygon]);
+}
+
+// Where the edge from `a` to `b` crosses the edge from `c` to `d`, as a fraction of the way from `a` to `b`
+fn edge_crossing(a: Vec2, b: Vec2, c: Vec2, d: Vec2) -> Option<f32> {
+    let (edge, other) = (b - a, d - c);
+    let denominator = edge.perp_dot(other);
+    if denominator == 0.0 {
+        return None;
+    }
+
+    let along_edge = (c - a).perp_dot(other) / denominator;
+    let along_other = (c - a).perp_dot(edge) / denominator;
+    ((0.0..=1.0).contains(&along_edge) && (0.0..=1.0).contains(&along_other)).then_some(along_edge)
+}
+
+// The pen's fence as runs of fencing, leaving out wherever a gate sits across it
+fn pen_walls(polygon: &[Vec2], gates: &[Vec<Vec2>]) -> Vec<Obstacle> {
+    let fence = |points: Vec<Vec2>, closed: bool| Obstacle {
+        kind: ObstacleKind::Fence,
+        points,
+        closed,
+    };
+    let in_gateway = |point: Vec2| gates.iter().any(|gate| polygon_contains(gate, point));
+
+    let mut runs: Vec<Vec<Vec2>> = Vec::new();
+    let mut run: Vec<Vec2> = Vec::new();
+    let mut gapped = false;
+
+    for (i, &a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        let point_at = |t: f32| match t {
+            t if t <= 0.0 => a,
+            t if t >= 1.0 => b,
+            t => a.lerp(b, t),
+        };
+
+        // Cut the edge wherever it goes in or out of a gate
+        let mut cuts = vec![0.0, 1.0];
+        for gate in gates {
+            for (j, &c) in gate.iter().enumerate() {
+                cuts.extend(edge_crossing(a, b, c, gate[(j + 1) % gate.len()]));
+            }
+        }
+        cuts.sort_by(f32::total_cmp);
+        cuts.dedup();
+
+        for pair in cuts.windows(2) {
+            let (start, end) = (point_at(pair[0]), point_at(pair[1]));
+            if in_gateway(start.lerp(end, 0.5)) {
+                gapped = true;
+                if run.len() > 1 {
+                    runs.push(std::mem::take(&mut run));
+                }
+                run.clear();
+                continue;
+            }
+            if run.last() != Some(&start) {
+                if run.len() > 1 {
+                    runs.push(std::mem::take(&mut run));
+                }
+                run = vec![start];
+            }
+            run.push(end);
+        }
+    }
+
+    if !gapped {
+        return vec![fence(polygon.to_vec(), true)];
+    }
+    if run.len() > 1 {
+        // The last run carries on into the first if the gap isn't at the first corner
+        match runs.first_mut() {
+            Some(first) if first.first() == run.last() => {
+                run.extend_from_slice(&first[1..]);
+                *first = run;
+            }
+            _ => runs.push(run),
+        }
+    }
+    runs.into_iter().map(|points| fence(points, false)).collect()
+}
+
+fn spawn_pen_with_gates(commands: &mut Commands, polygon: Vec<Vec2>, gates: &[Vec<Vec2>]) {
+    let walls = pen_walls(&polygon, gates);
+    let pen = commands.spawn((SpatialBundle::default(), Pen { polygon }, RunEntity)).id();
+
+    for wall in walls {
+        let entity = if wall.closed {
+            spawn_polygon_outline(commands, &wall.points, PEN_COLOR)
+        } else {
+            spawn_polyline(commands, &wall.points, PEN_COLOR)
+        };
+        commands.entity(entity).insert(wall);
+        commands.entity(pen).add_child(entity);
+    }
+
+    for gate_polygon in gates {
+        let gate = spawn_polygon_outline(commands, gate_polygon, GATE_COLOR);
+        commands.entity(gate).insert((
+            Gate {
+                pen,
+                polygon: gate_polygon.clone(),
+                open: true,
+            },
+            Obstacle {
+                kind: ObstacleKind::Fence,
+                points: Vec::new(),
+                closed: true,
+            },
+            Visibility::Hidden,
+            RunEntity,
+        ));
+    }
+}
+
+pub(crate) fn operate_gates(
+    mut gate_commands: EventReader<GateCommand>,
+    mut gate_query: Query<(&mut Gate, &mut Obstacle, &mut Visibility)>,
+) {
+    for command in gate_commands.iter() {
+        for (mut gate, mut obstacle, mut visibility) in &mut gate_query {
+            gate.obey(*command, &mut obstacle, &mut visibility);
+        }
+    }
+}
+
+pub fn detect_penned_sheep(
+    mut progress: ResMut<PenProgress>,
+    sheep_query: Query<(Entity, &Transform), With<Sheep>>,
+    pen_query: Query<(Entity, &Pen)>,
+    gate_query: Query<&Gate>,
+    mut penned_events: EventWriter<SheepPenned>,
+    mut escaped_events: EventWriter<SheepEscaped>,
+    mut complete_events: EventWriter<RunComplete>,
+) {
+    let mut penned = HashMap::default();
+
+    for (sheep, transform) in &sheep_query {
+        let position = transform.translation.truncate();
+        let pen = pen_query
+            .iter()
+            .find(|(_, pen)| pen.contains(position))
+            .map(|(entity, _)| entity);
+        let previous = progress.penned.get(&sheep).copied();
+
+        if previous != pen {
+            if let Some(pen) = previous {
+                progress.escapes += 1;
+                escaped_events.send(SheepEscaped { sheep, pen });
+            }
+            if let Some(pen) = pen {
+                penned_events.send(SheepPenned { sheep, pen });
+            }
+        }
+
+        if let Some(pen) = pen {
+            penned.insert(sheep, pen);
+        }
+    }
+
+    progress.penned = penned;
+
+    let sheep_count = sheep_query.iter().len();
+    let gates_shut = gate_query.iter().all(|gate| !gate.open);
+    if !progress.complete && sheep_count > 0 && progress.penned.len() == sheep_count && gates_shut {
+        progress.complete = true;
+        complete_events.send(RunComplete);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square() -> Vec<Vec2> {
+        vec![Vec2::new(0., 0.), Vec2::new(200., 0.), Vec2::new(200., 200.), Vec2::new(0., 200.)]
+    }
+
+    #[test]
+    fn walls_leave_a_gap_for_the_gate() {
+        let gate = vec![Vec2::new(80., -6.), Vec2::new(120., -6.), Vec2::new(120., 6.), Vec2::new(80., 6.)];
+        let walls = pen_walls(&square(), &[gate]);
+
+        let expected = [
+            Vec2::new(120., 0.),
+            Vec2::new(200., 0.),
+            Vec2::new(200., 200.),
+            Vec2::new(0., 200.),
+            Vec2::new(0., 0.),
+            Vec2::new(80., 0.),
+        ];
+        assert_eq!(walls.len(), 1);
+        assert!(!walls[0].closed);
+        assert_eq!(walls[0].points.len(), expected.len());
+        for (point, expected) in walls[0].points.iter().zip(expected) {
+            assert!(point.abs_diff_eq(expected, 0.001), "{} isn't {}", point, expected);
+        }
+    }
+
+    #[test]
+    fn a_pen_without_gates_is_fenced_all_round() {
+        let walls = pen_walls(&square(), &[]);
+        assert_eq!(walls.len(), 1);
+        assert!(walls[0].closed);
+        assert_eq!(walls[0].points, square());
+    }
+}
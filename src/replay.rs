@@ -0,0 +1,337 @@
+use std::path::Path;
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ai_handler::{handle_dogs, Handler};
+use crate::dog::{move_dog, Dog, DogOrder, Kennel};
+use crate::dog_target::steer_to_target;
+use crate::flocking::FlockingParams;
+use crate::flocking_model::FlockingModelKind;
+use crate::level::CurrentLevel;
+use crate::obstacle::Obstacle;
+use crate::pen::{operate_gates, Gate, GateCommand};
+use crate::rng::GameSeed;
+use crate::sheep::SheepCount;
+use crate::simulation::{advance_tick, SimulationSet, SimulationTick, TickRate};
+use crate::trial::GameMode;
+use crate::GameState;
+
+// Bumped whenever the replay format or what it records changes, older replays won't play back the same
+pub const REPLAY_VERSION: u32 = 4;
+// Where the last run is saved, next to wherever the game is started from
+#[cfg(not(target_arch = "wasm32"))]
+const REPLAY_PATH: &str = "replay.ron";
+
+pub struct ReplayPlugin;
+
+/// This plugin records every run so it can be played back exactly, for bug reports, coaching and
+/// regression tests. Every order the handler, keys, stick, mouse or whistle gave the dogs, and every
+/// command to the gates, is kept with the tick it took effect on, and the run is saved
+/// to `replay.ron` when it ends, along with the level, dogs, flock and game mode it was played with.
+/// Insert a [`Replay`] (with its seed, tick rate, sheep count and flocking settings) before the game
+/// plugins to play one back instead: its inputs are given again on the same ticks, while the player's
+/// own input is ignored and the computer can't take over. It has to be played on the level it was recorded on.
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        match app.world.remove_resource::<Replay>() {
+            Some(replay) => {
+                if !settings_match(&app.world, &replay) {
+                    warn!(
+                        "Replaying a run with seed {} at {} ticks a second, with {} sheep flocking as {}, \
+                         it won't play back the same without those",
+                        replay.seed,
+                        replay.tick_rate,
+                        replay.sheep_count,
+                        replay.flocking_params.model.name()
+                    );
+                }
+                app.insert_resource(ReplayPlayer::new(replay))
+                    .insert_resource(Handler::Player)
+                    // The menu picks the dogs and mode, the replay has to have the ones it was recorded with
+                    .add_systems(OnExit(GameState::Menu), use_replay_settings)
+                    .add_systems(OnEnter(GameState::Playing), start_replay)
+                    .add_systems(
+                        FixedUpdate,
+                        play_replay
+                            .after(handle_dogs)
+                            .after(steer_to_target)
+                            .before(move_dog)
+                            .in_set(SimulationSet::Step)
+                            .run_if(in_state(GameState::Playing)),
+                    );
+            }
+            None => {
+                app.init_resource::<ReplayRecorder>()
+                    .add_systems(OnEnter(GameState::Playing), start_recording)
+                    .add_systems(
+                        Update,
+                        record_gate_commands
+                            .after(operate_gates)
+                            .run_if(in_state(GameState::Playing)),
+                    )
+                    .add_systems(
+                        FixedUpdate,
+                        record_replay
+                            .before(advance_tick)
+                            .in_set(SimulationSet::Record)
+                            .run_if(in_state(GameState::Playing)),
+                    )
+                    .add_systems(OnExit(GameState::Playing), save_replay)
+                    .add_systems(Last, save_replay.run_if(on_event::<AppExit>()));
+            }
+        }
+    }
+}
+
+// Something the dogs or gates were told
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ReplayInput {
+    // An order given to the dog with this number
+    Dog { dog: usize, order: DogOrder },
+    Gates(GateCommand),
+}
+
+// An input and the tick it took effect on, counting from the first tick of the run
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ReplayEntry {
+    pub tick: u64,
+    pub input: ReplayInput,
+}
+
+// A recorded run: what it needs to start the same way, and every input in the order it was given
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    pub tick_rate: f32,
+    // Where the level was loaded from, or none for a field without one
+    pub level: Option<String>,
+    pub kennel: Kennel,
+    pub sheep_count: i32,
+    // Its `model` is the one the sheep flocked with, even if it was picked on the command line
+    pub flocking_params: FlockingParams,
+    pub mode: GameMode,
+    pub inputs: Vec<ReplayEntry>,
+}
+
+impl Replay {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let saved = std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let replay: Self = ron::from_str(&saved).map_err(|error| format!("{}: {}", path.display(), error))?;
+        if replay.version != REPLAY_VERSION {
+            return Err(format!(
+                "{} was recorded by replay version {}, this is version {}",
+                path.display(),
+                replay.version,
+                REPLAY_VERSION
+            ));
+        }
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let saved = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default()).map_err(|error| error.to_string())?;
+        std::fs::write(path, saved).map_err(|error| error.to_string())
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+pub(crate) struct ReplayRecorder {
+    replay: Option<Replay>,
+    // Gate commands given between ticks, waiting for the tick they take effect on
+    gate_commands: Vec<GateCommand>,
+    start_tick: u64,
+}
+
+impl ReplayRecorder {
+    // The run recorded so far, once it has started
+    pub(crate) fn replay(&self) -> Option<&Replay> {
+        self.replay.as_ref()
+    }
+}
+
+#[derive(Resource, Debug)]
+pub(crate) struct ReplayPlayer {
+    replay: Replay,
+    // The next input to play back
+    next: usize,
+    start_tick: u64,
+}
+
+impl ReplayPlayer {
+    fn new(replay: Replay) -> Self {
+        Self {
+            replay,
+            next: 0,
+            start_tick: 0,
+        }
+    }
+}
+
+// Run condition for the player's own input, which a replay stands in for
+pub(crate) fn not_replaying(player: Option<Res<ReplayPlayer>>) -> bool {
+    player.is_none()
+}
+
+// Whether everything the replay needs before the game plugins was inserted as it was recorded
+fn settings_match(world: &World, replay: &Replay) -> bool {
+    world.get_resource::<GameSeed>().map(|seed| seed.0) == Some(replay.seed)
+        && world.get_resource::<TickRate>().map(|tick_rate| tick_rate.0) == Some(replay.tick_rate)
+        && world.get_resource::<SheepCount>().map(|sheep_count| sheep_count.0) == Some(replay.sheep_count)
+        && world.get_resource::<FlockingModelKind>() == Some(&replay.flocking_params.model)
+        && world.get_resource::<FlockingParams>() == Some(&replay.flocking_params)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn start_recording(
+    mut recorder: ResMut<ReplayRecorder>,
+    tick: Res<SimulationTick>,
+    seed: Res<GameSeed>,
+    tick_rate: Res<TickRate>,
+    level: Option<Res<CurrentLevel>>,
+    kennel: Res<Kennel>,
+    sheep_count: Res<SheepCount>,
+    flocking_model: Res<FlockingModelKind>,
+    flocking_params: Res<FlockingParams>,
+    mode: Res<GameMode>,
+) {
+    let level = level.map(|level| level.0.id.clone()).filter(|id| !id.is_empty());
+    *recorder = ReplayRecorder {
+        replay: Some(Replay {
+            version: REPLAY_VERSION,
+            seed: seed.0,
+            tick_rate: tick_rate.0,
+            level,
+            kennel: kennel.clone(),
+            sheep_count: sheep_count.0,
+            flocking_params: FlockingParams {
+                model: *flocking_model,
+                ..flocking_params.clone()
+            },
+            mode: *mode,
+            inputs: Vec::new(),
+        }),
+        gate_commands: Vec::new(),
+        start_tick: tick.0,
+    };
+}
+
+// The gates are worked between ticks, so what they were told is kept for the next tick
+fn record_gate_commands(mut recorder: ResMut<ReplayRecorder>, mut gate_commands: EventReader<GateCommand>) {
+    recorder.gate_commands.extend(gate_commands.iter().copied());
+}
+
+// Runs at the end of every tick, and keeps every order given since the last as this tick's input
+fn record_replay(mut recorder: ResMut<ReplayRecorder>, tick: Res<SimulationTick>, mut dog_query: Query<&mut Dog>) {
+    let recorder = &mut *recorder;
+    let Some(replay) = recorder.replay.as_mut() else {
+        return;
+    };
+    let tick = tick.0 - recorder.start_tick;
+
+    // The gates were worked before any dog was told anything this tick
+    for command in recorder.gate_commands.drain(..) {
+        replay.inputs.push(ReplayEntry {
+            tick,
+            input: ReplayInput::Gates(command),
+        });
+    }
+
+    let mut dogs: Vec<Mut<Dog>> = dog_query.iter_mut().collect();
+    dogs.sort_by_key(|dog| dog.number());
+    for mut dog in dogs {
+        let number = dog.number();
+        for order in dog.take_orders() {
+            replay.inputs.push(ReplayEntry {
+                tick,
+                input: ReplayInput::Dog { dog: number, order },
+            });
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_replay(recorder: Res<ReplayRecorder>) {
+    let Some(replay) = recorder.replay.as_ref() else {
+        return;
+    };
+    match replay.save(REPLAY_PATH) {
+        Ok(()) => info!("Saved the replay to {}", REPLAY_PATH),
+        Err(error) => warn!("Couldn't save the replay to {}: {}", REPLAY_PATH, error),
+    }
+}
+
+// The web build has nowhere to keep it
+#[cfg(target_arch = "wasm32")]
+fn save_replay() {}
+
+fn use_replay_settings(
+    player: Res<ReplayPlayer>,
+    mut kennel: ResMut<Kennel>,
+    mut mode: ResMut<GameMode>,
+    mut handler: ResMut<Handler>,
+) {
+    *kennel = player.replay.kennel.clone();
+    *mode = player.replay.mode;
+    // Whoever handled the dogs, the replay gives their orders now
+    *handler = Handler::Player;
+}
+
+// A replay on the wrong field is meaningless, so rather than play it back wrong the game stops
+fn start_replay(
+    mut player: ResMut<ReplayPlayer>,
+    tick: Res<SimulationTick>,
+    level: Option<Res<CurrentLevel>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let level = level.map(|level| level.0.id.clone()).filter(|id| !id.is_empty());
+    if level != player.replay.level {
+        error!(
+            "The replay was recorded on level {:?} but this is {:?}, it can't be played back",
+            player.replay.level, level
+        );
+        exit.send(AppExit);
+        return;
+    }
+    info!("Replaying {} inputs", player.replay.inputs.len());
+    player.next = 0;
+    player.start_tick = tick.0;
+}
+
+// Gives the dogs and gates everything they were told on this tick, in the order it was given
+fn play_replay(
+    mut player: ResMut<ReplayPlayer>,
+    tick: Res<SimulationTick>,
+    mut dog_query: Query<&mut Dog>,
+    mut gate_query: Query<(&mut Gate, &mut Obstacle, &mut Visibility)>,
+) {
+    let player = &mut *player;
+    let tick = tick.0 - player.start_tick;
+
+    while let Some(entry) = player.replay.inputs.get(player.next).filter(|entry| entry.tick <= tick) {
+        match entry.input {
+            ReplayInput::Dog { dog: number, order } => {
+                if let Some(mut dog) = dog_query.iter_mut().find(|dog| dog.number() == number) {
+                    dog.obey(order);
+                }
+            }
+            ReplayInput::Gates(command) => {
+                for (mut gate, mut obstacle, mut visibility) in &mut gate_query {
+                    gate.obey(command, &mut obstacle, &mut visibility);
+                }
+            }
+        }
+        player.next += 1;
+        if player.next == player.replay.inputs.len() {
+            info!("Replay finished at tick {}", tick);
+        }
+    }
+
+    // Nothing is recorded while playing back, so the orders just given needn't be kept
+    for mut dog in &mut dog_query {
+        dog.take_orders();
+    }
+}
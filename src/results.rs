@@ -0,0 +1,142 @@
+use crate::loading::FontAssets;
+use crate::trial::Scorecard;
+use crate::{GameState, RunEntity};
+use bevy::prelude::*;
+
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+const FAULT_COLOR: Color = Color::rgb(0.9, 0.6, 0.5);
+const BUTTON_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const HOVERED_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+
+pub struct ResultsPlugin;
+
+/// This plugin shows the trial scorecard once the last phase is finished.
+/// The results are drawn over the field during the State `GameState::Results`, with buttons to run
+/// the trial again or go back to the menu. Leaving clears away the results and everything from the run.
+impl Plugin for ResultsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::Results), setup_results)
+            .add_systems(Update, click_results_buttons.run_if(in_state(GameState::Results)))
+            .add_systems(OnExit(GameState::Results), cleanup_results);
+    }
+}
+
+#[derive(Component)]
+struct ResultsScreen;
+
+#[derive(Component)]
+enum ResultsButton {
+    Retry,
+    Menu,
+}
+
+fn setup_results(mut commands: Commands, font_assets: Res<FontAssets>, scorecard: Res<Scorecard>) {
+    let style = |font_size: f32, color: Color| TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size,
+        color,
+    };
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                margin: UiRect::all(Val::Auto),
+                padding: UiRect::all(Val::Px(20.0)),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            background_color: Color::rgba(0.1, 0.1, 0.1, 0.8).into(),
+            ..Default::default()
+        })
+        .insert(ResultsScreen)
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section("Trial results", style(40.0, TEXT_COLOR)));
+
+            for score in &scorecard.phases {
+                parent.spawn(TextBundle::from_section(
+                    format!("{}: {} / {}", score.phase.name(), score.points(), score.phase.budget()),
+                    style(28.0, TEXT_COLOR),
+                ));
+                for fault in &score.faults {
+                    parent.spawn(TextBundle::from_section(
+                        format!("{} (-{})", fault.reason, fault.points),
+                        style(20.0, FAULT_COLOR),
+                    ));
+                }
+            }
+
+            parent.spawn(TextBundle::from_section(
+                format!("Total: {} / {}", scorecard.total(), scorecard.max_total()),
+                style(36.0, TEXT_COLOR),
+            ));
+
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        margin: UiRect {
+                            top: Val::Px(10.0),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for (label, button) in [("Retry", ResultsButton::Retry), ("Menu", ResultsButton::Menu)] {
+                        parent
+                            .spawn(ButtonBundle {
+                                style: Style {
+                                    width: Val::Px(160.0),
+                                    height: Val::Px(50.0),
+                                    margin: UiRect::all(Val::Px(10.0)),
+                                    justify_content: JustifyContent::Center,
+                                    align_items: AlignItems::Center,
+                                    ..Default::default()
+                                },
+                                background_color: BUTTON_COLOR.into(),
+                                ..Default::default()
+                            })
+                            .insert(button)
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(label, style(40.0, TEXT_COLOR)));
+                            });
+                    }
+                });
+        });
+}
+
+fn click_results_buttons(
+    mut state: ResMut<NextState<GameState>>,
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &ResultsButton),
+        (Changed<Interaction>, With<Button>),
+    >,
+) {
+    for (interaction, mut color, button) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => match button {
+                // Same mode, handler and dogs as the run just finished
+                ResultsButton::Retry => state.set(GameState::Playing),
+                ResultsButton::Menu => state.set(GameState::Menu),
+            },
+            Interaction::Hovered => {
+                *color = HOVERED_COLOR.into();
+            }
+            Interaction::None => {
+                *color = BUTTON_COLOR.into();
+            }
+        }
+    }
+}
+
+fn cleanup_results(
+    mut commands: Commands,
+    screen: Query<Entity, With<ResultsScreen>>,
+    run_query: Query<Entity, With<RunEntity>>,
+) {
+    commands.entity(screen.single()).despawn_recursive();
+    for entity in &run_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
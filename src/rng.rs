@@ -0,0 +1,64 @@
+use bevy::prelude::*;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::GameState;
+
+pub struct RngPlugin;
+
+/// This plugin provides the single source of randomness for gameplay.
+/// Insert a [`GameSeed`] before adding the game plugins to replay a run exactly,
+/// otherwise a fresh seed is picked and logged so the run can be reproduced later.
+/// Every run after the first starts over from the same seed, so a retry is the same trial again.
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        let seed = match app.world.get_resource::<GameSeed>() {
+            Some(seed) => *seed,
+            None => {
+                let seed = GameSeed(rand::random());
+                app.insert_resource(seed);
+                seed
+            }
+        };
+        info!("Game seed: {}", seed.0);
+
+        app.insert_resource(GameRng::from_seed(seed.0))
+            .add_systems(OnExit(GameState::Results), reseed_rng);
+    }
+}
+
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct GameSeed(pub u64);
+
+// Every random draw that affects gameplay goes through this, so the same seed
+// always gives the same sheep layout and trajectories
+#[derive(Resource)]
+pub struct GameRng(ChaCha8Rng);
+
+impl GameRng {
+    pub fn from_seed(seed: u64) -> Self {
+        Self(ChaCha8Rng::seed_from_u64(seed))
+    }
+}
+
+fn reseed_rng(seed: Res<GameSeed>, mut rng: ResMut<GameRng>) {
+    *rng = GameRng::from_seed(seed.0);
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.0.try_fill_bytes(dest)
+    }
+}
@@ -1,184 +1,267 @@
 use bevy::prelude::*;
-use rand::{random, Rng};
+use rand::Rng;
 
+use crate::dog::{move_dog, Dog};
 use crate::field::Field;
-use crate::GameState;
+use crate::flocking::FlockingParams;
+use crate::flocking_model::{FlockingModelKind, Neighbour, Neighbourhood};
+use crate::{GameState, RunEntity};
+use crate::level::CurrentLevel;
 use crate::loading::TextureAssets;
+use crate::obstacle::{resolve_movement, Obstacle};
+use crate::rng::GameRng;
+use crate::simulation::{Interpolated, SimulationSet};
+use crate::spatial_hash::SpatialHash;
 use bevy::math::Vec2;
 
+// How close a sheep's middle can get to an obstacle
+const SHEEP_RADIUS: f32 = 12.0;
 
 pub struct SheepPlugin;
 
 
 #[derive(Component, Debug)]
 pub struct Sheep {
-    id: i32,
     velocity: Vec2,
-    close_d: Vec2,
-    vel_avg: Vec2,
-    num_neighbors: i32,
-    pos_avg: Vec2,
-    bounced: (bool, bool),
+    // Steering from the sheep around it, as the flocking model sees it
+    social: Vec2,
     bias: Vec2,
+    flee: Vec2,
+    pressure: f32,
+    // Pull towards the better grazing on the tiles around the sheep
+    graze: Vec2,
+    // Push away from any obstacles close by
+    avoid: Vec2,
 }
 
 impl Sheep {
-    pub fn new() -> Self {
-        let random_velocity = Vec2::new(
-            (-0.5 + random::<f32>()) * 100.0,
-            (-0.5 + random::<f32>()) * 100.0,
-        );
-
-        Self {
-            id: 0,
-            velocity: random_velocity,
-            close_d: Vec2::ZERO,
-            vel_avg: Vec2::ZERO,
-            num_neighbors: 0,
-            pos_avg: Vec2::ZERO,
-            bounced: (false, false),
-            bias: Vec2::ZERO,
-        }
+    pub fn clear(&mut self) {
+        self.social = Vec2::ZERO;
+        self.flee = Vec2::ZERO;
+        self.pressure = 0.0;
+        self.graze = Vec2::ZERO;
+        self.avoid = Vec2::ZERO;
     }
+}
 
-    pub fn clear(&mut self) {
-        self.close_d = Vec2::ZERO;
-        self.vel_avg = Vec2::ZERO;
-        self.pos_avg = Vec2::ZERO;
-        self.num_neighbors = 0;
+
+// How many sheep are spawned when play starts
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct SheepCount(pub i32);
+
+impl Default for SheepCount {
+    fn default() -> Self {
+        Self(199)
     }
 }
 
+// Summary of where the flock is, for anything that needs to work relative to it
+#[derive(Resource, Default, Debug)]
+pub struct Flock {
+    pub centre: Vec2,
+    // Distance from the centre to the furthest sheep
+    pub radius: f32,
+    // Position of that furthest sheep, the one most likely to have been missed
+    pub straggler: Vec2,
+    pub count: usize,
+}
+
 
+/// This plugin spawns the flock and moves it every tick. How the sheep react to each other
+/// is up to the [`FlockingModelKind`] resource. One inserted up front stays put, otherwise it
+/// follows the `model` in the flocking params, so editing `sheep.flocking.ron` switches models live.
 impl Plugin for SheepPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(GameState::Playing), spawn_sheep)
+        if !app.world.contains_resource::<FlockingModelKind>() {
+            app.init_resource::<FlockingModelKind>()
+                .add_systems(Update, follow_flocking_model);
+        }
+
+        app.insert_resource(SpatialHash::new(FlockingParams::default().visible_distance))
+            .init_resource::<Flock>()
+            .init_resource::<SheepCount>()
+            .add_systems(OnEnter(GameState::Playing), spawn_sheep)
             // .add_systems(Update, flock.before(move_sheep).run_if(in_state(GameState::Playing)))
-            .add_systems(Update, move_and_flock_sheep.run_if(in_state(GameState::Playing)));
+            .add_systems(
+                FixedUpdate,
+                (update_spatial_hash, move_and_flock_sheep, update_flock)
+                    .chain()
+                    // The sheep react to where the dogs have just moved, always in that order so runs replay exactly
+                    .after(move_dog)
+                    .in_set(SimulationSet::Step)
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+fn follow_flocking_model(params: Res<FlockingParams>, mut flocking_model: ResMut<FlockingModelKind>) {
+    if params.is_changed() && *flocking_model != params.model {
+        info!("Sheep flocking with the {} model", params.model.name());
+        *flocking_model = params.model;
     }
 }
 
-fn spawn_sheep(mut commands: Commands, textures: Res<TextureAssets>, field: Res<Field>) {
+// Textures are optional so the flock can also be simulated headless
+fn spawn_sheep(
+    mut commands: Commands,
+    textures: Option<Res<TextureAssets>>,
+    level: Option<Res<CurrentLevel>>,
+    sheep_count: Res<SheepCount>,
+    mut rng: ResMut<GameRng>,
+) {
     const BIAS_STRENGTH: f32 = 0.008;
 
     let bias_a = Vec2::new(
-        (-0.5 + random::<f32>()) * BIAS_STRENGTH,
-        (-0.5 + random::<f32>()) * BIAS_STRENGTH,
+        (-0.5 + rng.gen::<f32>()) * BIAS_STRENGTH,
+        (-0.5 + rng.gen::<f32>()) * BIAS_STRENGTH,
     );
     let bias_b = Vec2::new(
-        (-0.5 + random::<f32>()) * BIAS_STRENGTH,
-        (-0.5 + random::<f32>()) * BIAS_STRENGTH,
+        (-0.5 + rng.gen::<f32>()) * BIAS_STRENGTH,
+        (-0.5 + rng.gen::<f32>()) * BIAS_STRENGTH,
     );
 
-    for i in 1..200 {
+    for i in 1..=sheep_count.0 {
         let rand_vel = Vec2::new(
-            (-0.5 + random::<f32>()) * 10.0,
-            (-0.5 + random::<f32>()) * 10.0,
-        );
-        let rand_pos = Vec2::new(
-            (-0.5 + random::<f32>()) * 1000.0,
-            (-0.5 + random::<f32>()) * 1000.0,
+            (-0.5 + rng.gen::<f32>()) * 10.0,
+            (-0.5 + rng.gen::<f32>()) * 10.0,
         );
+        // Levels say where sheep start, otherwise they are scattered around the middle of the field
+        let spawns = level.as_ref().map(|level| level.0.sheep_spawns.as_slice()).unwrap_or_default();
+        let rand_pos = if spawns.is_empty() {
+            Vec2::new(
+                (-0.5 + rng.gen::<f32>()) * 1000.0,
+                (-0.5 + rng.gen::<f32>()) * 1000.0,
+            )
+        } else {
+            let spawn = spawns[rng.gen_range(0..spawns.len())];
+            spawn.min + spawn.size() * Vec2::new(rng.gen::<f32>(), rng.gen::<f32>())
+        };
 
         commands
             .spawn(SpriteBundle {
-                texture: textures.sheep.clone(),
+                texture: textures.as_ref().map(|textures| textures.sheep.clone()).unwrap_or_default(),
                 transform: Transform::from_translation(Vec3::new(rand_pos.x, rand_pos.y, 0.0)).with_scale(Vec3::new(0.1, 0.1, 0.1)),
                 ..Default::default()
             })
+            .insert(Interpolated::new(Vec3::new(rand_pos.x, rand_pos.y, 0.0)))
+            .insert(RunEntity)
             .insert(Sheep {
-                id: i,
                 velocity: rand_vel,
-                close_d: Vec2::ZERO,
-                vel_avg: Vec2::ZERO,
-                num_neighbors: 0,
-                pos_avg: Vec2::ZERO,
-                bounced: (false, false),
+                social: Vec2::ZERO,
                 bias: match i % 8 {
                     0 => bias_a,
                     1 => bias_b,
                     _ => Vec2::ZERO,
                 },
+                flee: Vec2::ZERO,
+                pressure: 0.0,
+                graze: Vec2::ZERO,
+                avoid: Vec2::ZERO,
             });
     }
 }
 
-// Calculates interaction between a pair of sheep for alignment and cohesion
-fn calculate_pair_interaction(
-    a_transform: &mut Transform,
-    a_sheep: &mut Sheep,
-    b_transform: &mut Transform,
-    b_sheep: &mut Sheep,
-    protected_distance: f32,
-    visible_distance: f32,
+// Calculates how a dog inside the sheep's flight zone pushes it away,
+// the closer the dog the harder the push and the higher the pressure
+fn calculate_dog_interaction(
+    sheep: &mut Sheep,
+    position: Vec2,
+    dog_position: Vec2,
+    flight_zone_radius: f32,
 ) {
-    let d = (a_transform.translation - b_transform.translation).truncate();
+    let d = position - dog_position;
     let distance = d.length();
 
-    if distance <= protected_distance {
-        // Avoid
-        a_sheep.close_d += d;
-        b_sheep.close_d -= d;
-    } else if distance <= visible_distance {
-        // Align and Cohere
-        a_sheep.num_neighbors += 1;
-        b_sheep.num_neighbors += 1;
+    if distance >= flight_zone_radius {
+        return;
+    }
 
-        a_sheep.vel_avg += b_sheep.velocity;
-        b_sheep.vel_avg += a_sheep.velocity;
+    let closeness = 1.0 - distance / flight_zone_radius;
+    sheep.flee += d.normalize_or_zero() * closeness;
+    sheep.pressure = sheep.pressure.max(closeness);
+}
+
+// Sums the directions to the surrounding tiles, weighted by how much better (or worse)
+// their grazing is than the tile the sheep is standing on
+fn calculate_grazing_pull(field: &Field, position: Vec2) -> Vec2 {
+    let Some(tile) = field.world_to_tile(position) else {
+        return Vec2::ZERO;
+    };
+    let here = field.terrain_at(tile).grazing();
+
+    field
+        .neighbours(tile)
+        .map(|neighbour| {
+            let direction = (field.tile_to_world(neighbour) - field.tile_to_world(tile)).normalize();
+            direction * (field.terrain_at(neighbour).grazing() - here)
+        })
+        .sum()
+}
 
-        a_sheep.pos_avg += b_transform.translation.truncate();
-        b_sheep.pos_avg += a_transform.translation.truncate();
+// Steers the sheep away from obstacles within `range` of it, harder the closer they are,
+// so it turns well before it has to be stopped
+fn calculate_obstacle_avoidance(sheep: &mut Sheep, position: Vec2, obstacles: &[&Obstacle], range: f32) {
+    for obstacle in obstacles {
+        let Some((distance, out)) = obstacle.clearance(position) else {
+            continue;
+        };
+        if distance < range {
+            sheep.avoid += out * (1.0 - distance.max(0.0) / range);
+        }
     }
 }
 
 // Modifies the velocity of a single sheep based on flocking rules
 fn apply_flocking_rule_for_single_sheep(
-    sheep_transform: &mut Transform,
     sheep: &mut Sheep,
-    align_factor: f32,
-    centering_factor: f32,
-    avoid_factor: f32,
-    max_speed: f32,
+    params: &FlockingParams,
+    rng: &mut impl Rng,
 ) {
-    let mut adjustment = Vec2::ZERO;
-
-    if sheep.num_neighbors > 0 {
-        // Alignment and Centering adjustments
-        let vel_avg = sheep.vel_avg / sheep.num_neighbors as f32;
-        let pos_avg = sheep.pos_avg / sheep.num_neighbors as f32;
+    // A dog close by makes the sheep move faster
+    let max_speed = params.max_speed * (1.0 + sheep.pressure * params.pressure_speed_boost);
 
-        let align = vel_avg - sheep.velocity;
-        adjustment += align * align_factor;
-
-        let center = pos_avg - sheep_transform.translation.truncate();
-        adjustment += center * centering_factor;
-    }
-
-    // Avoidance and Bias adjustments
-    adjustment += sheep.close_d * avoid_factor;
+    // Flocking, Flight and Bias adjustments
+    let mut adjustment = sheep.social;
+    adjustment += sheep.flee * params.flee_factor;
     adjustment += sheep.bias;
+    // A sheep with a dog on it stops caring about the grass
+    adjustment += sheep.graze * params.grazing_factor * (1.0 - sheep.pressure);
+    adjustment += sheep.avoid * params.obstacle_factor;
 
     // Apply adjustments and clamp the velocity
     sheep.velocity += adjustment;
     sheep.velocity = clamp_velocity(sheep.velocity, max_speed);  // Assume you've implemented clamp_velocity
-    wander(sheep);
+    wander(sheep, params.wander_force, rng);
 }
 
+// Rebuilds the spatial hash from the current sheep positions so neighbour
+// lookups this tick only have to visit nearby cells
+fn update_spatial_hash(
+    params: Res<FlockingParams>,
+    mut spatial_hash: ResMut<SpatialHash>,
+    sheep_query: Query<(Entity, &Transform), With<Sheep>>,
+) {
+    spatial_hash.clear(params.visible_distance);
+
+    for (entity, transform) in &sheep_query {
+        spatial_hash.insert(entity, transform.translation.truncate());
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn move_and_flock_sheep(
     field: ResMut<Field>,
-    time: Res<Time>,
+    fixed_time: Res<FixedTime>,
+    params: Res<FlockingParams>,
+    flocking_model: Res<FlockingModelKind>,
+    spatial_hash: Res<SpatialHash>,
+    mut rng: ResMut<GameRng>,
     mut sheep_query: Query<(&mut Transform, &mut Sheep)>,
+    dog_query: Query<&Transform, (With<Dog>, Without<Sheep>)>,
+    obstacle_query: Query<&Obstacle>,
 ) {
-    const MAX_SPEED: f32 = 2.0;
-    const BOUNDARY_DAMPING: f32 = 0.1;
-    const SPEED: f32 = 150.0;
-
-    const PROTECTED_DISTANCE: f32 = 50.0;
-    const VISIBLE_DISTANCE: f32 = 100.0;
-    const AVOID_FACTOR: f32 = 0.002;
-    const ALIGN_FACTOR: f32 = 0.01;
-    const CENTERING_FACTOR: f32 = 0.0001;
+    let obstacles: Vec<&Obstacle> = obstacle_query.iter().collect();
+    let delta_seconds = fixed_time.period.as_secs_f32();
 
     // Initialize field boundaries
     let max_x = 0.8 * field.width / 2.0;
@@ -192,53 +275,133 @@ fn move_and_flock_sheep(
     }
 
 
-    let mut combinations = sheep_query.iter_combinations_mut();
+    // Snapshot velocities up front, the query is borrowed mutably while accumulating
+    let velocities: Vec<Vec2> = spatial_hash
+        .entries()
+        .iter()
+        .map(|(entity, _)| {
+            sheep_query
+                .get(*entity)
+                .map_or(Vec2::ZERO, |(_, sheep)| sheep.velocity)
+        })
+        .collect();
+
+    // Dogs first, some models only flock once a dog has the sheep worried
+    let dog_positions: Vec<Vec2> = dog_query.iter().map(|transform| transform.translation.truncate()).collect();
+    for (transform, mut sheep) in sheep_query.iter_mut() {
+        for dog_position in dog_positions.iter() {
+            calculate_dog_interaction(&mut sheep, transform.translation.truncate(), *dog_position, params.flight_zone_radius);
+        }
+    }
+
+    // Neighbours are handed to the model in insertion order, which is the order a pairwise
+    // pass over the query would visit them, so the boids sums come out identical
+    let model = flocking_model.model();
+    let sight_distance = model.sight_distance(&params);
+    let mut indices = Vec::new();
+    let mut neighbours = Vec::new();
+    for (index, (entity, position)) in spatial_hash.entries().iter().enumerate() {
+        let Ok((_, mut sheep)) = sheep_query.get_mut(*entity) else {
+            continue;
+        };
+
+        spatial_hash.query(*position, sight_distance, &mut indices);
+        neighbours.clear();
+        neighbours.extend(indices.iter().filter(|&&other| other != index).map(|&other| Neighbour {
+            position: spatial_hash.entries()[other].1,
+            velocity: velocities[other],
+        }));
+
+        let neighbourhood = Neighbourhood {
+            position: *position,
+            velocity: sheep.velocity,
+            pressure: sheep.pressure,
+            neighbours: &neighbours,
+        };
+        sheep.social = model.steer(&neighbourhood, &params);
+    }
 
-    while let Some([(mut a_transform, mut a_sheep), (mut b_transform, mut b_sheep)]) = combinations.fetch_next() {
-        calculate_pair_interaction(&mut a_transform, &mut a_sheep, &mut b_transform, &mut b_sheep, PROTECTED_DISTANCE, VISIBLE_DISTANCE);
+    for (transform, mut sheep) in sheep_query.iter_mut() {
+        let position = transform.translation.truncate();
+        sheep.graze = calculate_grazing_pull(&field, position);
+        calculate_obstacle_avoidance(&mut sheep, position, &obstacles, params.obstacle_distance);
     }
 
-    for (mut transform, mut sheep) in sheep_query.iter_mut() {
-        apply_flocking_rule_for_single_sheep(&mut transform, &mut sheep, ALIGN_FACTOR, CENTERING_FACTOR, AVOID_FACTOR, MAX_SPEED);
+    for (_, mut sheep) in sheep_query.iter_mut() {
+        apply_flocking_rule_for_single_sheep(&mut sheep, &params, &mut *rng);
     }
 
     // Movement logic
     for (mut sheep_transform, mut sheep) in &mut sheep_query {
+        // Heavy going slows the sheep down
+        let ground = field
+            .tile_at(sheep_transform.translation.truncate())
+            .unwrap_or_default()
+            .sheep_speed();
+        let speed = params.speed * ground;
+
         // Calculate new position based on current velocity
 
         let new_position = Vec2 {
-            x: sheep_transform.translation.x + sheep.velocity.x * SPEED * time.delta_seconds(),
-            y: sheep_transform.translation.y + sheep.velocity.y * SPEED * time.delta_seconds(),
+            x: sheep_transform.translation.x + sheep.velocity.x * speed * delta_seconds,
+            y: sheep_transform.translation.y + sheep.velocity.y * speed * delta_seconds,
         };
 
         // Boundary checks and gradual damping
         if new_position.x >= max_x {
-            sheep.velocity.x -= BOUNDARY_DAMPING;
+            sheep.velocity.x -= params.boundary_damping;
         } else if new_position.x <= min_x {
-            sheep.velocity.x += BOUNDARY_DAMPING;
+            sheep.velocity.x += params.boundary_damping;
         }
 
         if new_position.y >= max_y {
-            sheep.velocity.y -= BOUNDARY_DAMPING;
+            sheep.velocity.y -= params.boundary_damping;
         } else if new_position.y <= min_y {
-            sheep.velocity.y += BOUNDARY_DAMPING;
+            sheep.velocity.y += params.boundary_damping;
         }
 
         // Calculate new position based on adjusted velocity
         let adjusted = Vec2 {
-            x: sheep_transform.translation.x + sheep.velocity.x * SPEED * time.delta_seconds(),
-            y: sheep_transform.translation.y + sheep.velocity.y * SPEED * time.delta_seconds(),
+            x: sheep_transform.translation.x + sheep.velocity.x * speed * delta_seconds,
+            y: sheep_transform.translation.y + sheep.velocity.y * speed * delta_seconds,
         };
 
+        // Fences, trees and the like stop the sheep however hard it is pushed
+        let resolved = resolve_movement(&obstacles, sheep_transform.translation.truncate(), adjusted, SHEEP_RADIUS);
 
         // Update position
-        sheep_transform.translation = adjusted.extend(0.0);
+        sheep_transform.translation = resolved.extend(0.0);
+    }
+}
+
+
+fn update_flock(mut flock: ResMut<Flock>, sheep_query: Query<&Transform, With<Sheep>>) {
+    let positions: Vec<Vec2> = sheep_query.iter().map(|transform| transform.translation.truncate()).collect();
+
+    flock.count = positions.len();
+    if positions.is_empty() {
+        flock.centre = Vec2::ZERO;
+        flock.radius = 0.0;
+        flock.straggler = Vec2::ZERO;
+        return;
+    }
+
+    let centre = positions.iter().copied().sum::<Vec2>() / positions.len() as f32;
+    flock.centre = centre;
+    flock.radius = 0.0;
+    flock.straggler = centre;
+    for position in positions {
+        let distance = position.distance(centre);
+        if distance > flock.radius {
+            flock.radius = distance;
+            flock.straggler = position;
+        }
     }
 }
 
 
 fn clamp_velocity(velocity: Vec2, max_speed: f32) -> Vec2 {
-    let mut velocity = velocity.clone();
+    let mut velocity = velocity;
 
     if velocity.x > max_speed {
         velocity.x = max_speed;
@@ -256,14 +419,13 @@ fn clamp_velocity(velocity: Vec2, max_speed: f32) -> Vec2 {
 }
 
 /// Produces a random unit vector.
-fn random_unit_vector() -> Vec2 {
-    let angle: f32 = rand::thread_rng().gen_range(0.0..std::f32::consts::TAU); // TAU is 2*PI
+fn random_unit_vector(rng: &mut impl Rng) -> Vec2 {
+    let angle: f32 = rng.gen_range(0.0..std::f32::consts::TAU); // TAU is 2*PI
     Vec2::new(angle.cos(), angle.sin())
 }
 
-fn wander(sheep: &mut Sheep) {
-    const WANDER_FORCE: f32 = 0.1;
+fn wander(sheep: &mut Sheep, wander_force: f32, rng: &mut impl Rng) {
     // Some method to produce a random unit vector
-    let random_dir = random_unit_vector();
-    sheep.velocity += random_dir * WANDER_FORCE;
+    let random_dir = random_unit_vector(rng);
+    sheep.velocity += random_dir * wander_force;
 }
\ No newline at end of file
@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+
+use crate::actions::Actions;
+
+// Simulation ticks per second unless a `TickRate` is inserted before the game plugins
+const DEFAULT_TICK_RATE: f32 = 60.0;
+
+pub struct SimulationPlugin;
+
+/// This plugin runs sheep and dog movement in `FixedUpdate` so the flock behaves the
+/// same regardless of frame rate. Simulated entities carry an [`Interpolated`] component
+/// and their sprites are smoothed between the last two ticks when rendering.
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        let tick_rate = match app.world.get_resource::<TickRate>() {
+            Some(tick_rate) => *tick_rate,
+            None => {
+                app.insert_resource(TickRate(DEFAULT_TICK_RATE));
+                TickRate(DEFAULT_TICK_RATE)
+            }
+        };
+
+        app.insert_resource(FixedTime::new_from_secs(1.0 / tick_rate.0))
+            .init_resource::<SimulationTick>()
+            .configure_sets(
+                FixedUpdate,
+                (
+                    SimulationSet::Restore,
+                    SimulationSet::Step,
+                    SimulationSet::Evaluate,
+                    SimulationSet::Record,
+                )
+                    .chain(),
+            )
+            .add_systems(FixedUpdate, restore_simulated_translation.in_set(SimulationSet::Restore))
+            .add_systems(
+                FixedUpdate,
+                (record_simulated_translation, advance_tick).in_set(SimulationSet::Record),
+            )
+            .add_systems(Update, (toggle_pause.run_if(resource_exists::<Actions>()), interpolate_translation));
+    }
+}
+
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct TickRate(pub f32);
+
+// Number of fixed ticks simulated so far
+#[derive(Resource, Default, Debug)]
+pub struct SimulationTick(pub u64);
+
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+pub enum SimulationSet {
+    // Puts simulated transforms back where the last tick left them
+    Restore,
+    // Gameplay systems that move things go here
+    Step,
+    // Systems that judge where this tick left things, like pens and scoring
+    Evaluate,
+    // Remembers where this tick left everything
+    Record,
+}
+
+// Where a simulated entity was at the end of the last two ticks.
+// The `Transform` only holds the simulated position during `FixedUpdate`,
+// the rest of the frame it holds the interpolated one.
+#[derive(Component, Debug)]
+pub struct Interpolated {
+    previous: Vec3,
+    current: Vec3,
+}
+
+impl Interpolated {
+    pub fn new(translation: Vec3) -> Self {
+        Self {
+            previous: translation,
+            current: translation,
+        }
+    }
+}
+
+fn restore_simulated_translation(mut query: Query<(&mut Transform, &mut Interpolated)>) {
+    for (mut transform, mut interpolated) in &mut query {
+        transform.translation = interpolated.current;
+        interpolated.previous = interpolated.current;
+    }
+}
+
+fn record_simulated_translation(mut query: Query<(&Transform, &mut Interpolated)>) {
+    for (transform, mut interpolated) in &mut query {
+        interpolated.current = transform.translation;
+    }
+}
+
+pub fn advance_tick(mut tick: ResMut<SimulationTick>) {
+    tick.0 += 1;
+}
+
+// With the clock stopped no time builds up for the fixed ticks, so the whole simulation holds still
+fn toggle_pause(actions: Res<Actions>, mut time: ResMut<Time>) {
+    if actions.toggle_pause {
+        if time.is_paused() {
+            time.unpause();
+        } else {
+            time.pause();
+        }
+        info!("Paused: {}", time.is_paused());
+    }
+}
+
+fn interpolate_translation(
+    fixed_time: Res<FixedTime>,
+    mut query: Query<(&mut Transform, &Interpolated)>,
+) {
+    let alpha = (fixed_time.accumulated().as_secs_f32() / fixed_time.period.as_secs_f32()).clamp(0.0, 1.0);
+
+    for (mut transform, interpolated) in &mut query {
+        transform.translation = interpolated.previous.lerp(interpolated.current, alpha);
+    }
+}
@@ -0,0 +1,184 @@
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+/// A uniform grid that buckets entities by position so neighbour lookups only
+/// need to visit nearby cells instead of every other entity.
+///
+/// Entries are stored in insertion order and referred to by their index, so
+/// callers can keep parallel per-entry data alongside the hash.
+#[derive(Resource)]
+pub struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<IVec2, Vec<usize>>,
+    entries: Vec<(Entity, Vec2)>,
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size: cell_size.max(f32::EPSILON),
+            cells: HashMap::default(),
+            entries: vec![],
+        }
+    }
+
+    /// Empties the hash and sets the cell size used for the next round of inserts.
+    pub fn clear(&mut self, cell_size: f32) {
+        self.cell_size = cell_size.max(f32::EPSILON);
+        self.cells.clear();
+        self.entries.clear();
+    }
+
+    pub fn insert(&mut self, entity: Entity, position: Vec2) {
+        let index = self.entries.len();
+        self.entries.push((entity, position));
+        self.cells
+            .entry(self.cell_for(position))
+            .or_default()
+            .push(index);
+    }
+
+    pub fn entries(&self) -> &[(Entity, Vec2)] {
+        &self.entries
+    }
+
+    /// Fills `out` with the indices of every entry in the cells overlapping the
+    /// square of half-width `radius` around `position`, in ascending order.
+    ///
+    /// The result is a superset of the entries within `radius`; callers still
+    /// need to check the actual distance.
+    pub fn query(&self, position: Vec2, radius: f32, out: &mut Vec<usize>) {
+        out.clear();
+
+        let min = self.cell_for(position - Vec2::splat(radius));
+        let max = self.cell_for(position + Vec2::splat(radius));
+
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                if let Some(indices) = self.cells.get(&IVec2::new(x, y)) {
+                    out.extend_from_slice(indices);
+                }
+            }
+        }
+
+        out.sort_unstable();
+    }
+
+    fn cell_for(&self, position: Vec2) -> IVec2 {
+        (position / self.cell_size).floor().as_ivec2()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::flocking::FlockingParams;
+    use crate::flocking_model::{Boids, FlockingModel, Neighbour, Neighbourhood};
+
+    // A seeded scatter of sheep, plus some sat right on cell edges and pairs exactly the
+    // visible distance apart, either side of the origin
+    fn layout(visible_distance: f32) -> Vec<(Vec2, Vec2)> {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut sheep: Vec<(Vec2, Vec2)> = (0..300)
+            .map(|_| {
+                let position = Vec2::new(rng.gen_range(-600.0..600.0), rng.gen_range(-600.0..600.0));
+                let velocity = Vec2::new(rng.gen_range(-2.0..2.0), rng.gen_range(-2.0..2.0));
+                (position, velocity)
+            })
+            .collect();
+
+        let edges = [
+            Vec2::ZERO,
+            Vec2::new(visible_distance, 0.0),
+            Vec2::new(-visible_distance, 0.0),
+            Vec2::new(0.0, visible_distance),
+            Vec2::new(visible_distance, visible_distance),
+            Vec2::new(-visible_distance, -visible_distance),
+            Vec2::new(2.0 * visible_distance, -visible_distance),
+            Vec2::new(350.0, 250.0),
+            Vec2::new(350.0, 250.0 + visible_distance),
+            Vec2::new(350.0 - visible_distance, 250.0),
+            Vec2::new(-250.0, 425.0),
+            Vec2::new(-250.0 + visible_distance, 425.0),
+        ];
+        sheep.extend(edges.iter().enumerate().map(|(i, &position)| (position, Vec2::new(i as f32 * 0.1, 1.0))));
+        sheep
+    }
+
+    fn neighbourhood<'a>(sheep: &[(Vec2, Vec2)], index: usize, neighbours: &'a [Neighbour]) -> Neighbourhood<'a> {
+        Neighbourhood {
+            position: sheep[index].0,
+            velocity: sheep[index].1,
+            pressure: 0.0,
+            neighbours,
+        }
+    }
+
+    #[test]
+    fn spatial_hash_matches_pairwise() {
+        let params = FlockingParams::default();
+        let sheep = layout(params.visible_distance);
+
+        let mut hash = SpatialHash::new(params.visible_distance);
+        for (index, (position, _)) in sheep.iter().enumerate() {
+            hash.insert(Entity::from_raw(index as u32), *position);
+        }
+
+        let mut indices = Vec::new();
+        for (index, (position, _)) in sheep.iter().enumerate() {
+            hash.query(*position, params.visible_distance, &mut indices);
+            let hashed: Vec<Neighbour> = indices
+                .iter()
+                .filter(|&&other| other != index)
+                .map(|&other| Neighbour {
+                    position: sheep[other].0,
+                    velocity: sheep[other].1,
+                })
+                .collect();
+            let pairwise: Vec<Neighbour> = sheep
+                .iter()
+                .enumerate()
+                .filter(|(other, _)| *other != index)
+                .map(|(_, &(position, velocity))| Neighbour { position, velocity })
+                .collect();
+
+            let visible = |neighbours: &[Neighbour]| -> Vec<Vec2> {
+                neighbours
+                    .iter()
+                    .map(|other| other.position)
+                    .filter(|other| position.distance(*other) <= params.visible_distance)
+                    .collect()
+            };
+            assert_eq!(visible(&hashed), visible(&pairwise), "sheep {} at {}", index, position);
+
+            let from_hash = Boids.steer(&neighbourhood(&sheep, index, &hashed), &params);
+            let from_pairs = Boids.steer(&neighbourhood(&sheep, index, &pairwise), &params);
+            assert_eq!(from_hash, from_pairs, "sheep {} at {}", index, position);
+        }
+    }
+
+    #[test]
+    fn sheep_at_the_visible_distance_are_neighbours() {
+        let params = FlockingParams::default();
+        let mut hash = SpatialHash::new(params.visible_distance);
+        hash.insert(Entity::from_raw(0), Vec2::ZERO);
+        hash.insert(Entity::from_raw(1), Vec2::new(params.visible_distance, 0.0));
+        hash.insert(Entity::from_raw(2), Vec2::new(0.0, -params.visible_distance));
+
+        let mut indices = Vec::new();
+        let mut visible = |position: Vec2| {
+            hash.query(position, params.visible_distance, &mut indices);
+            indices
+                .iter()
+                .copied()
+                .filter(|&other| position.distance(hash.entries()[other].1) <= params.visible_distance)
+                .collect::<Vec<usize>>()
+        };
+        assert_eq!(visible(Vec2::ZERO), vec![0, 1, 2]);
+        assert_eq!(visible(Vec2::new(params.visible_distance, 0.0)), vec![0, 1]);
+        assert_eq!(visible(Vec2::new(0.0, -params.visible_distance)), vec![0, 2]);
+    }
+}
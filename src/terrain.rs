@@ -0,0 +1,133 @@
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+
+// Roughly how many tiles across the hills and wet patches of generated ground are
+const TERRAIN_FEATURE_TILES: f64 = 12.0;
+const TERRAIN_OCTAVES: usize = 4;
+
+// What the ground is like on a tile. Each kind slows the sheep and dog down by its own amount
+// and is worth more or less to a grazing sheep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Terrain {
+    #[default]
+    Grass,
+    LushGrass,
+    LongGrass,
+    Mud,
+    Water,
+    Rock,
+}
+
+impl Terrain {
+    pub const ALL: [Terrain; 6] = [
+        Terrain::Grass,
+        Terrain::LushGrass,
+        Terrain::LongGrass,
+        Terrain::Mud,
+        Terrain::Water,
+        Terrain::Rock,
+    ];
+
+    // The class given to tiles of this kind in a Tiled tileset
+    pub fn from_class(class: &str) -> Option<Self> {
+        match class {
+            "grass" => Some(Terrain::Grass),
+            "lush_grass" => Some(Terrain::LushGrass),
+            "long_grass" => Some(Terrain::LongGrass),
+            "mud" => Some(Terrain::Mud),
+            "water" => Some(Terrain::Water),
+            "rock" => Some(Terrain::Rock),
+            _ => None,
+        }
+    }
+
+    // Fraction of its usual speed a sheep keeps on this ground
+    pub fn sheep_speed(self) -> f32 {
+        match self {
+            Terrain::Grass | Terrain::LushGrass => 1.0,
+            Terrain::LongGrass => 0.8,
+            Terrain::Mud => 0.5,
+            Terrain::Water => 0.3,
+            Terrain::Rock => 0.6,
+        }
+    }
+
+    // Fraction of its usual speed the dog keeps on this ground
+    pub fn dog_speed(self) -> f32 {
+        match self {
+            Terrain::Grass | Terrain::LushGrass => 1.0,
+            Terrain::LongGrass => 0.7,
+            Terrain::Mud => 0.6,
+            Terrain::Water => 0.4,
+            Terrain::Rock => 0.8,
+        }
+    }
+
+    // How much a sheep wants to graze here, sheep drift towards the better grazing around them
+    pub fn grazing(self) -> f32 {
+        match self {
+            Terrain::Grass => 0.5,
+            Terrain::LushGrass => 1.0,
+            Terrain::LongGrass => 0.8,
+            Terrain::Mud | Terrain::Water | Terrain::Rock => 0.0,
+        }
+    }
+}
+
+// Lays out varied ground for a grid of tiles, row by row from the top left.
+// One noise field is the lie of the land (water in the hollows, rock on the tops),
+// the other how wet it is (mud and long grass in the wet, lush grass in the dry).
+// The same seed always gives the same ground.
+pub fn generate_terrain(columns: usize, rows: usize, seed: u64) -> Vec<Terrain> {
+    let seed = (seed ^ (seed >> 32)) as u32;
+    let elevation = Fbm::<Perlin>::new(seed)
+        .set_octaves(TERRAIN_OCTAVES)
+        .set_frequency(1.0 / TERRAIN_FEATURE_TILES);
+    let wetness = Fbm::<Perlin>::new(seed.wrapping_add(1))
+        .set_octaves(TERRAIN_OCTAVES)
+        .set_frequency(1.0 / TERRAIN_FEATURE_TILES);
+
+    let mut terrain = Vec::with_capacity(columns * rows);
+    for row in 0..rows {
+        for column in 0..columns {
+            let point = [column as f64, row as f64];
+            let height = elevation.get(point);
+            let wet = wetness.get(point);
+
+            terrain.push(if height < -0.45 {
+                Terrain::Water
+            } else if height > 0.5 {
+                Terrain::Rock
+            } else if height < -0.3 || wet > 0.45 {
+                Terrain::Mud
+            } else if wet > 0.2 {
+                Terrain::LongGrass
+            } else if wet < -0.2 {
+                Terrain::LushGrass
+            } else {
+                Terrain::Grass
+            });
+        }
+    }
+
+    terrain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_same_seed_gives_the_same_ground() {
+        let first = generate_terrain(32, 24, 42);
+        assert_eq!(first.len(), 32 * 24);
+        assert_eq!(first, generate_terrain(32, 24, 42));
+    }
+
+    #[test]
+    fn another_seed_gives_other_ground() {
+        let first = generate_terrain(32, 24, 42);
+        for seed in [0, 43, u64::MAX] {
+            assert_ne!(first, generate_terrain(32, 24, seed), "seed {} gave the same ground as 42", seed);
+        }
+    }
+}
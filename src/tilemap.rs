@@ -0,0 +1,217 @@
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::sprite::{MaterialMesh2dBundle, TextureAtlasBuilder};
+use bevy::utils::HashMap;
+
+use crate::field::Field;
+use crate::loading::TextureAssets;
+use crate::terrain::Terrain;
+use crate::GameState;
+
+// Tiles along each side of a chunk
+const CHUNK_SIZE: usize = 16;
+// Just behind the sprites at z 0, and still in front of the 2d camera's far plane at -0.1
+const TILE_Z: f32 = -0.05;
+// Keeps each tile's UVs half a texel inside its slot so the neighbouring texture in the atlas doesn't bleed in
+const UV_INSET: f32 = 0.5;
+
+pub struct TilemapPlugin;
+
+/// This plugin draws the field's terrain as one mesh per chunk of tiles, all sharing a single
+/// texture atlas. Chunks are only rebuilt when the field changes size, repainting terrain just
+/// rewrites the UVs of the existing meshes.
+impl Plugin for TilemapPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TileChunks>()
+            .add_systems(OnEnter(GameState::Playing), build_terrain_atlas)
+            .add_systems(
+                Update,
+                sync_tile_chunks.run_if(in_state(GameState::Playing).and_then(resource_exists::<TerrainAtlas>())),
+            );
+    }
+}
+
+#[derive(Resource)]
+struct TerrainAtlas {
+    material: Handle<ColorMaterial>,
+    // Where each terrain's texture sits in the atlas, in UV space
+    uvs: HashMap<Terrain, Rect>,
+}
+
+// A square block of tiles drawn as a single mesh
+struct TileChunk {
+    entity: Entity,
+    mesh: Handle<Mesh>,
+    first_column: usize,
+    first_row: usize,
+    columns: usize,
+    rows: usize,
+}
+
+#[derive(Resource, Default)]
+struct TileChunks {
+    // The columns, rows and tile size the chunks were built for
+    layout: Option<(usize, usize, f32)>,
+    chunks: Vec<TileChunk>,
+}
+
+fn terrain_texture(textures: &TextureAssets, terrain: Terrain) -> Handle<Image> {
+    match terrain {
+        Terrain::Grass => textures.texture_tile_grass_1.clone(),
+        Terrain::LushGrass => textures.texture_tile_grass_2.clone(),
+        Terrain::LongGrass => textures.texture_tile_long_grass.clone(),
+        Terrain::Mud => textures.texture_tile_mud.clone(),
+        Terrain::Water => textures.texture_tile_water.clone(),
+        Terrain::Rock => textures.texture_tile_rock.clone(),
+    }
+}
+
+// Packs every terrain texture into one atlas, so a chunk can be drawn with a single material
+fn build_terrain_atlas(
+    mut commands: Commands,
+    textures: Option<Res<TextureAssets>>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    // Without textures (e.g. headless) there is nothing to draw the field with
+    let Some(textures) = textures else {
+        return;
+    };
+
+    let mut builder = TextureAtlasBuilder::default();
+    for terrain in Terrain::ALL {
+        let handle = terrain_texture(&textures, terrain);
+        let Some(image) = images.get(&handle) else {
+            warn!("The {:?} texture isn't loaded, the field won't be drawn", terrain);
+            return;
+        };
+        builder.add_texture(handle, image);
+    }
+
+    let atlas = match builder.finish(&mut images) {
+        Ok(atlas) => atlas,
+        Err(error) => {
+            error!("Couldn't build the terrain atlas: {}", error);
+            return;
+        }
+    };
+
+    let uvs = Terrain::ALL
+        .into_iter()
+        .filter_map(|terrain| {
+            let index = atlas.get_texture_index(&terrain_texture(&textures, terrain))?;
+            let rect = atlas.textures[index];
+            let uv = Rect::from_corners(
+                (rect.min + UV_INSET) / atlas.size,
+                (rect.max - UV_INSET) / atlas.size,
+            );
+            Some((terrain, uv))
+        })
+        .collect();
+
+    commands.insert_resource(TerrainAtlas {
+        material: materials.add(ColorMaterial::from(atlas.texture)),
+        uvs,
+    });
+}
+
+// The quads of a chunk, with everything but the UVs, which depend on the terrain
+fn chunk_mesh(field: &Field, chunk: &TileChunk) -> Mesh {
+    let half = field.tile_size / 2.;
+    let mut positions = Vec::with_capacity(chunk.columns * chunk.rows * 4);
+    let mut indices = Vec::with_capacity(chunk.columns * chunk.rows * 6);
+
+    for row in chunk.first_row..chunk.first_row + chunk.rows {
+        for column in chunk.first_column..chunk.first_column + chunk.columns {
+            let centre = field.tile_to_world(UVec2::new(column as u32, row as u32));
+            let first = positions.len() as u32;
+
+            // Anticlockwise from the bottom left
+            positions.extend([
+                [centre.x - half, centre.y - half, 0.],
+                [centre.x + half, centre.y - half, 0.],
+                [centre.x + half, centre.y + half, 0.],
+                [centre.x - half, centre.y + half, 0.],
+            ]);
+            indices.extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+        }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0., 0., 1.]; positions.len()]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+// Texture coordinates matching the order `chunk_mesh` lays the corners out in
+fn chunk_uvs(field: &Field, atlas: &TerrainAtlas, chunk: &TileChunk) -> Vec<[f32; 2]> {
+    let mut uvs = Vec::with_capacity(chunk.columns * chunk.rows * 4);
+
+    for row in chunk.first_row..chunk.first_row + chunk.rows {
+        for column in chunk.first_column..chunk.first_column + chunk.columns {
+            let uv = atlas.uvs.get(&field.terrain_at(UVec2::new(column as u32, row as u32))).copied().unwrap_or_default();
+            // Image rows run top down, so the bottom of the quad takes the bottom of the texture
+            uvs.extend([
+                [uv.min.x, uv.max.y],
+                [uv.max.x, uv.max.y],
+                [uv.max.x, uv.min.y],
+                [uv.min.x, uv.min.y],
+            ]);
+        }
+    }
+
+    uvs
+}
+
+fn sync_tile_chunks(
+    mut commands: Commands,
+    field: Res<Field>,
+    atlas: Res<TerrainAtlas>,
+    mut tile_chunks: ResMut<TileChunks>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    if !field.is_changed() && !atlas.is_added() {
+        return;
+    }
+
+    // A field of a different shape needs new chunks, anything else is just new terrain
+    let layout = (field.columns, field.rows, field.tile_size);
+    if tile_chunks.layout != Some(layout) {
+        for chunk in tile_chunks.chunks.drain(..) {
+            commands.entity(chunk.entity).despawn();
+            meshes.remove(&chunk.mesh);
+        }
+
+        for first_row in (0..field.rows).step_by(CHUNK_SIZE) {
+            for first_column in (0..field.columns).step_by(CHUNK_SIZE) {
+                let mut chunk = TileChunk {
+                    entity: Entity::PLACEHOLDER,
+                    mesh: Handle::default(),
+                    first_column,
+                    first_row,
+                    columns: CHUNK_SIZE.min(field.columns - first_column),
+                    rows: CHUNK_SIZE.min(field.rows - first_row),
+                };
+                chunk.mesh = meshes.add(chunk_mesh(&field, &chunk));
+                chunk.entity = commands
+                    .spawn(MaterialMesh2dBundle {
+                        mesh: chunk.mesh.clone().into(),
+                        material: atlas.material.clone(),
+                        transform: Transform::from_xyz(0., 0., TILE_Z),
+                        ..Default::default()
+                    })
+                    .id();
+                tile_chunks.chunks.push(chunk);
+            }
+        }
+
+        tile_chunks.layout = Some(layout);
+    }
+
+    for chunk in &tile_chunks.chunks {
+        if let Some(mesh) = meshes.get_mut(&chunk.mesh) {
+            mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, chunk_uvs(&field, &atlas, chunk));
+        }
+    }
+}
@@ -0,0 +1,523 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::dog::{Dog, HandlerPost};
+use crate::level::Level;
+use crate::pen::{detect_penned_sheep, spawn_polygon_outline, PenProgress};
+use crate::sheep::{Flock, Sheep};
+use crate::simulation::{SimulationSet, SimulationTick};
+use crate::{GameState, RunEntity};
+
+const COURSE_COLOR: Color = Color::rgb(0.9, 0.9, 0.85);
+const DRIVE_GATE_POST_SIZE: f32 = 12.0;
+const SHED_RING_SEGMENTS: usize = 32;
+
+// The outrun ends once the dog is within this angle of straight behind the flock (about 30 degrees)...
+const BALANCE_COS: f32 = 0.85;
+// ...and no further than this outside the flock
+const BALANCE_MARGIN: f32 = 150.0;
+// Coming closer than this to any sheep on the outrun is a tight outrun, costing a point per step
+const TIGHT_OUTRUN_DISTANCE: f32 = 150.0;
+const TIGHT_OUTRUN_STEP: f32 = 10.0;
+// The lift is over once the flock centre has moved this far
+const LIFT_DISTANCE: f32 = 60.0;
+// A flock centre moving faster than this during the lift has bolted, costing a point per step over
+const BOLT_SPEED: f32 = 120.0;
+const BOLT_STEP: f32 = 20.0;
+// The fetch is over once the flock centre is this close to the handler
+const FETCH_END_DISTANCE: f32 = 200.0;
+// A point is lost for every step the flock centre strays from the line between the lift and the handler
+const FETCH_LINE_STEP: f32 = 25.0;
+const MISSED_DRIVE_GATE_POINTS: u32 = 10;
+// A shed needs the dog in among the flock with a clear gap around it
+const SHED_DOG_DISTANCE: f32 = 60.0;
+const SHED_GAP: f32 = 80.0;
+const ESCAPED_SHEEP_POINTS: u32 = 2;
+// Past its par time a phase loses a point for every step, and is abandoned at its time limit
+const OVERTIME_STEP_SECONDS: f32 = 10.0;
+const TIME_LIMIT_PAR_MULTIPLE: f32 = 3.0;
+
+pub struct TrialPlugin;
+
+/// This plugin runs a sheepdog trial when the player picks trial mode from the menu.
+/// It follows the dog and flock through the outrun, lift, fetch, drive, shed and pen,
+/// taking points off each phase for faults, and shows the [`Scorecard`] once the pen is shut.
+impl Plugin for TrialPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameMode>()
+            .init_resource::<TrialCourse>()
+            .init_resource::<Scorecard>()
+            .add_systems(
+                OnEnter(GameState::Playing),
+                (start_trial, spawn_course).run_if(resource_equals(GameMode::Trial)),
+            )
+            .add_systems(
+                FixedUpdate,
+                judge_trial
+                    .after(detect_penned_sheep)
+                    .in_set(SimulationSet::Evaluate)
+                    .run_if(in_state(GameState::Playing).and_then(resource_exists::<TrialRun>())),
+            );
+    }
+}
+
+// Picked from the menu before play starts
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameMode {
+    #[default]
+    FreePlay,
+    Trial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrialPhase {
+    // The dog runs out wide to get behind the sheep
+    Outrun,
+    // The dog's first contact, getting the sheep moving
+    Lift,
+    // The sheep are brought straight to the handler
+    Fetch,
+    // The sheep are driven away from the handler through the drive gates
+    Drive,
+    // The dog splits the flock inside the shedding ring
+    Shed,
+    // The sheep are put in the pen and the gate is shut
+    Pen,
+}
+
+impl TrialPhase {
+    pub fn name(self) -> &'static str {
+        match self {
+            TrialPhase::Outrun => "Outrun",
+            TrialPhase::Lift => "Lift",
+            TrialPhase::Fetch => "Fetch",
+            TrialPhase::Drive => "Drive",
+            TrialPhase::Shed => "Shed",
+            TrialPhase::Pen => "Pen",
+        }
+    }
+
+    // The points a phase starts with
+    pub fn budget(self) -> u32 {
+        match self {
+            TrialPhase::Outrun => 20,
+            TrialPhase::Lift => 10,
+            TrialPhase::Fetch => 20,
+            TrialPhase::Drive => 30,
+            TrialPhase::Shed => 10,
+            TrialPhase::Pen => 10,
+        }
+    }
+
+    fn par_seconds(self) -> f32 {
+        match self {
+            TrialPhase::Outrun => 45.,
+            TrialPhase::Lift => 15.,
+            TrialPhase::Fetch => 60.,
+            TrialPhase::Drive => 120.,
+            TrialPhase::Shed => 60.,
+            TrialPhase::Pen => 90.,
+        }
+    }
+
+    fn next(self) -> Option<TrialPhase> {
+        match self {
+            TrialPhase::Outrun => Some(TrialPhase::Lift),
+            TrialPhase::Lift => Some(TrialPhase::Fetch),
+            TrialPhase::Fetch => Some(TrialPhase::Drive),
+            TrialPhase::Drive => Some(TrialPhase::Shed),
+            TrialPhase::Shed => Some(TrialPhase::Pen),
+            TrialPhase::Pen => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Fault {
+    pub reason: String,
+    pub points: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct PhaseScore {
+    pub phase: TrialPhase,
+    pub faults: Vec<Fault>,
+}
+
+impl PhaseScore {
+    // What is left of the budget after the faults, never below zero
+    pub fn points(&self) -> u32 {
+        let lost: u32 = self.faults.iter().map(|fault| fault.points).sum();
+        self.phase.budget().saturating_sub(lost)
+    }
+}
+
+// One entry per phase, filled in as each phase is finished
+#[derive(Resource, Default, Debug)]
+pub struct Scorecard {
+    pub phases: Vec<PhaseScore>,
+    pub finished: bool,
+}
+
+impl Scorecard {
+    pub fn total(&self) -> u32 {
+        self.phases.iter().map(PhaseScore::points).sum()
+    }
+
+    pub fn max_total(&self) -> u32 {
+        self.phases.iter().map(|score| score.phase.budget()).sum()
+    }
+}
+
+// Where the trial course is laid out in world space. A level drawing its own drive gates or
+// shedding ring replaces this one, otherwise it's a course for the default field.
+#[derive(Resource, Debug, Clone)]
+pub struct TrialCourse {
+    // The posts of each drive gate, to be taken in order
+    pub drive_gates: Vec<(Vec2, Vec2)>,
+    pub shed_ring_centre: Vec2,
+    pub shed_ring_radius: f32,
+}
+
+impl Default for TrialCourse {
+    fn default() -> Self {
+        Self {
+            // A drive away to the left, then a cross drive back over the middle of the field
+            drive_gates: vec![
+                (Vec2::new(-650., -350.), Vec2::new(-650., -150.)),
+                (Vec2::new(-100., 150.), Vec2::new(100., 150.)),
+            ],
+            shed_ring_centre: Vec2::new(250., 350.),
+            shed_ring_radius: 150.,
+        }
+    }
+}
+
+impl TrialCourse {
+    // The course drawn on a level, if it has one, with anything it leaves out from the default course
+    pub fn from_level(level: &Level) -> Option<Self> {
+        if level.drive_gates.is_empty() && level.shed_ring.is_none() {
+            return None;
+        }
+
+        let mut course = Self::default();
+        if !level.drive_gates.is_empty() {
+            course.drive_gates = level.drive_gates.clone();
+        }
+        if let Some((centre, radius)) = level.shed_ring {
+            course.shed_ring_centre = centre;
+            course.shed_ring_radius = radius;
+        }
+        Some(course)
+    }
+}
+
+// The phase in progress and what has been measured of it so far
+#[derive(Resource, Debug)]
+struct TrialRun {
+    phase: TrialPhase,
+    phase_started: u64,
+    previous_centre: Vec2,
+    // Where the flock stood when the dog reached it, the start of the fetch line
+    lift_point: Vec2,
+    closest_outrun: f32,
+    fastest_lift: f32,
+    widest_fetch: f32,
+    next_drive_gate: usize,
+    missed_drive_gates: Vec<usize>,
+    escapes_at_start: usize,
+}
+
+impl TrialRun {
+    fn new(phase: TrialPhase, tick: u64, flock_centre: Vec2, lift_point: Vec2, escapes: usize) -> Self {
+        Self {
+            phase,
+            phase_started: tick,
+            previous_centre: flock_centre,
+            lift_point,
+            closest_outrun: f32::MAX,
+            fastest_lift: 0.,
+            widest_fetch: 0.,
+            next_drive_gate: 0,
+            missed_drive_gates: Vec::new(),
+            escapes_at_start: escapes,
+        }
+    }
+
+    // Turns what was measured during the phase into faults
+    fn faults(&self, seconds: f32, escapes: usize) -> Vec<Fault> {
+        let mut faults = Vec::new();
+        let mut fault = |reason: String, points: u32| {
+            if points > 0 {
+                faults.push(Fault { reason, points });
+            }
+        };
+
+        match self.phase {
+            TrialPhase::Outrun => fault(
+                "Tight outrun".to_string(),
+                steps_over(TIGHT_OUTRUN_DISTANCE - self.closest_outrun, TIGHT_OUTRUN_STEP),
+            ),
+            TrialPhase::Lift => fault(
+                "Sheep bolted on the lift".to_string(),
+                steps_over(self.fastest_lift - BOLT_SPEED, BOLT_STEP),
+            ),
+            TrialPhase::Fetch => fault(
+                "Fetch strayed off line".to_string(),
+                steps_over(self.widest_fetch, FETCH_LINE_STEP),
+            ),
+            TrialPhase::Drive => {
+                for gate in &self.missed_drive_gates {
+                    fault(format!("Missed drive gate {}", gate + 1), MISSED_DRIVE_GATE_POINTS);
+                }
+            }
+            TrialPhase::Shed => {}
+            TrialPhase::Pen => fault(
+                "Sheep escaped the pen".to_string(),
+                (escapes - self.escapes_at_start) as u32 * ESCAPED_SHEEP_POINTS,
+            ),
+        }
+
+        let par = self.phase.par_seconds();
+        if seconds >= par * TIME_LIMIT_PAR_MULTIPLE {
+            fault("Ran out of time".to_string(), self.phase.budget());
+        } else {
+            fault("Over time".to_string(), steps_over(seconds - par, OVERTIME_STEP_SECONDS));
+        }
+
+        faults
+    }
+}
+
+// How many whole steps `amount` covers, or zero if it is negative
+fn steps_over(amount: f32, step: f32) -> u32 {
+    (amount / step).floor().max(0.) as u32
+}
+
+// Where the path from `from` to `to` crosses the line through the posts `a` and `b`,
+// as a fraction of the way from `a` to `b`. Between 0 and 1 means it went through the gate.
+fn gate_crossing(from: Vec2, to: Vec2, a: Vec2, b: Vec2) -> Option<f32> {
+    let path = to - from;
+    let gate = b - a;
+    let denominator = path.perp_dot(gate);
+    if denominator == 0. {
+        return None;
+    }
+
+    let along_path = (a - from).perp_dot(gate) / denominator;
+    let along_gate = (a - from).perp_dot(path) / denominator;
+    (0.0..=1.0).contains(&along_path).then_some(along_gate)
+}
+
+fn start_trial(mut commands: Commands, tick: Res<SimulationTick>, flock: Res<Flock>, pen_progress: Res<PenProgress>) {
+    commands.insert_resource(Scorecard::default());
+    commands.insert_resource(TrialRun::new(
+        TrialPhase::Outrun,
+        tick.0,
+        flock.centre,
+        flock.centre,
+        pen_progress.escapes,
+    ));
+}
+
+fn spawn_course(mut commands: Commands, course: Res<TrialCourse>) {
+    for (a, b) in &course.drive_gates {
+        for post in [a, b] {
+            let half = DRIVE_GATE_POST_SIZE / 2.;
+            let square = [
+                *post + Vec2::new(-half, -half),
+                *post + Vec2::new(half, -half),
+                *post + Vec2::new(half, half),
+                *post + Vec2::new(-half, half),
+            ];
+            let post = spawn_polygon_outline(&mut commands, &square, COURSE_COLOR);
+            commands.entity(post).insert(RunEntity);
+        }
+    }
+
+    let ring: Vec<Vec2> = (0..SHED_RING_SEGMENTS)
+        .map(|i| {
+            let angle = i as f32 / SHED_RING_SEGMENTS as f32 * std::f32::consts::TAU;
+            course.shed_ring_centre + Vec2::from_angle(angle) * course.shed_ring_radius
+        })
+        .collect();
+    let ring = spawn_polygon_outline(&mut commands, &ring, COURSE_COLOR);
+    commands.entity(ring).insert(RunEntity);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn judge_trial(
+    mut commands: Commands,
+    tick: Res<SimulationTick>,
+    fixed_time: Res<FixedTime>,
+    flock: Res<Flock>,
+    handler_post: Res<HandlerPost>,
+    course: Res<TrialCourse>,
+    pen_progress: Res<PenProgress>,
+    mut run: ResMut<TrialRun>,
+    mut scorecard: ResMut<Scorecard>,
+    mut state: ResMut<NextState<GameState>>,
+    dog_query: Query<&Transform, With<Dog>>,
+    sheep_query: Query<&Transform, With<Sheep>>,
+) {
+    // With a brace out, the dog nearest the flock is the one being judged
+    let Some(dog) = dog_query
+        .iter()
+        .map(|transform| transform.translation.truncate())
+        .min_by(|a, b| a.distance_squared(flock.centre).total_cmp(&b.distance_squared(flock.centre)))
+    else {
+        return;
+    };
+    let period = fixed_time.period.as_secs_f32();
+    let seconds = (tick.0 - run.phase_started) as f32 * period;
+    let nearest_sheep = sheep_query
+        .iter()
+        .map(|transform| transform.translation.truncate().distance(dog))
+        .fold(f32::MAX, f32::min);
+
+    let done = match run.phase {
+        TrialPhase::Outrun => {
+            run.closest_outrun = run.closest_outrun.min(nearest_sheep);
+            let behind = (dog - flock.centre)
+                .normalize_or_zero()
+                .dot((flock.centre - handler_post.0).normalize_or_zero());
+            behind >= BALANCE_COS && dog.distance(flock.centre) <= flock.radius + BALANCE_MARGIN
+        }
+        TrialPhase::Lift => {
+            let speed = flock.centre.distance(run.previous_centre) / period;
+            run.fastest_lift = run.fastest_lift.max(speed);
+            flock.centre.distance(run.lift_point) >= LIFT_DISTANCE
+        }
+        TrialPhase::Fetch => {
+            let line = (handler_post.0 - run.lift_point).normalize_or_zero();
+            let off_line = (flock.centre - run.lift_point).perp_dot(line).abs();
+            run.widest_fetch = run.widest_fetch.max(off_line);
+            flock.centre.distance(handler_post.0) <= FETCH_END_DISTANCE
+        }
+        TrialPhase::Drive => {
+            if let Some((a, b)) = course.drive_gates.get(run.next_drive_gate) {
+                if let Some(crossing) = gate_crossing(run.previous_centre, flock.centre, *a, *b) {
+                    if !(0.0..=1.0).contains(&crossing) {
+                        let gate = run.next_drive_gate;
+                        run.missed_drive_gates.push(gate);
+                    }
+                    run.next_drive_gate += 1;
+                }
+            }
+            run.next_drive_gate >= course.drive_gates.len()
+        }
+        TrialPhase::Shed => {
+            let in_ring = |position: Vec2| position.distance(course.shed_ring_centre) <= course.shed_ring_radius;
+            in_ring(flock.centre)
+                && in_ring(dog)
+                && dog.distance(flock.centre) <= SHED_DOG_DISTANCE
+                && nearest_sheep >= SHED_GAP
+        }
+        TrialPhase::Pen => pen_progress.complete,
+    };
+    run.previous_centre = flock.centre;
+
+    let timed_out = seconds >= run.phase.par_seconds() * TIME_LIMIT_PAR_MULTIPLE;
+    if !done && !timed_out {
+        return;
+    }
+
+    let score = PhaseScore {
+        phase: run.phase,
+        faults: run.faults(seconds, pen_progress.escapes),
+    };
+    info!("{} finished with {} of {} points", score.phase.name(), score.points(), score.phase.budget());
+    scorecard.phases.push(score);
+
+    match run.phase.next() {
+        Some(next) => {
+            let lift_point = if run.phase == TrialPhase::Outrun { flock.centre } else { run.lift_point };
+            *run = TrialRun::new(next, tick.0, flock.centre, lift_point, pen_progress.escapes);
+        }
+        None => {
+            scorecard.finished = true;
+            commands.remove_resource::<TrialRun>();
+            state.set(GameState::Results);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(phase: TrialPhase) -> TrialRun {
+        TrialRun::new(phase, 0, Vec2::ZERO, Vec2::ZERO, 0)
+    }
+
+    fn score(phase: TrialPhase, faults: Vec<Fault>) -> u32 {
+        PhaseScore { phase, faults }.points()
+    }
+
+    #[test]
+    fn steps_over_counts_whole_steps() {
+        assert_eq!(steps_over(-5., 10.), 0);
+        assert_eq!(steps_over(0., 10.), 0);
+        assert_eq!(steps_over(9.99, 10.), 0);
+        assert_eq!(steps_over(10., 10.), 1);
+        assert_eq!(steps_over(29.99, 10.), 2);
+        assert_eq!(steps_over(30., 10.), 3);
+    }
+
+    #[test]
+    fn gates_are_crossed_either_way() {
+        let (a, b) = (Vec2::new(0., -50.), Vec2::new(0., 50.));
+
+        assert_eq!(gate_crossing(Vec2::new(-10., 25.), Vec2::new(10., 25.), a, b), Some(0.75));
+        assert_eq!(gate_crossing(Vec2::new(10., 25.), Vec2::new(-10., 25.), a, b), Some(0.75));
+        // Going along the line of the gate, or stopping short of it, isn't a crossing
+        assert_eq!(gate_crossing(Vec2::new(0., -100.), Vec2::new(0., 100.), a, b), None);
+        assert_eq!(gate_crossing(Vec2::new(-10., 0.), Vec2::new(-1., 0.), a, b), None);
+    }
+
+    #[test]
+    fn passing_outside_the_posts_misses_the_gate() {
+        let (a, b) = (Vec2::new(0., -50.), Vec2::new(0., 50.));
+
+        let above = gate_crossing(Vec2::new(-10., 80.), Vec2::new(10., 80.), a, b).unwrap();
+        let below = gate_crossing(Vec2::new(10., -60.), Vec2::new(-10., -60.), a, b).unwrap();
+        assert!(above > 1.);
+        assert!(below < 0.);
+    }
+
+    #[test]
+    fn every_fault_comes_off_the_budget() {
+        let mut drive = run(TrialPhase::Drive);
+        drive.missed_drive_gates = vec![0, 1];
+        let faults = drive.faults(0., 0);
+        assert_eq!(faults.len(), 2);
+        assert_eq!(score(TrialPhase::Drive, faults), 30 - 2 * MISSED_DRIVE_GATE_POINTS);
+
+        // 25 inside the tight outrun distance is two whole steps
+        let mut outrun = run(TrialPhase::Outrun);
+        outrun.closest_outrun = TIGHT_OUTRUN_DISTANCE - 25.;
+        assert_eq!(score(TrialPhase::Outrun, outrun.faults(0., 0)), 20 - 2);
+
+        // Three sheep got out of the pen, one of them before the pen phase started
+        let mut pen = run(TrialPhase::Pen);
+        pen.escapes_at_start = 1;
+        assert_eq!(score(TrialPhase::Pen, pen.faults(0., 3)), 10 - 2 * ESCAPED_SHEEP_POINTS);
+    }
+
+    #[test]
+    fn a_clean_phase_keeps_its_budget() {
+        let mut outrun = run(TrialPhase::Outrun);
+        outrun.closest_outrun = TIGHT_OUTRUN_DISTANCE;
+        assert!(outrun.faults(TrialPhase::Outrun.par_seconds(), 0).is_empty());
+        assert_eq!(score(TrialPhase::Outrun, Vec::new()), 20);
+    }
+
+    #[test]
+    fn time_over_par_costs_points_until_the_limit() {
+        let shed = run(TrialPhase::Shed);
+        let par = TrialPhase::Shed.par_seconds();
+
+        assert_eq!(score(TrialPhase::Shed, shed.faults(par + OVERTIME_STEP_SECONDS * 2., 0)), 10 - 2);
+        assert_eq!(score(TrialPhase::Shed, shed.faults(par * TIME_LIMIT_PAR_MULTIPLE, 0)), 0);
+    }
+}
@@ -0,0 +1,32 @@
+use rusty_sheep::{HeadlessConfig, HeadlessSimulation};
+
+fn simulate(seed: u64, ticks: u64) -> HeadlessSimulation {
+    let mut simulation = HeadlessSimulation::new(HeadlessConfig {
+        seed,
+        sheep_count: 60,
+        ..Default::default()
+    });
+    simulation.run(ticks);
+    simulation
+}
+
+#[test]
+fn same_seed_gives_the_same_run() {
+    let mut first = simulate(42, 300);
+    let mut second = simulate(42, 300);
+
+    assert_eq!(first.tick(), 300);
+    assert_eq!(first.sheep_positions(), second.sheep_positions());
+    assert_eq!(first.flock().centre, second.flock().centre);
+}
+
+#[test]
+fn different_seed_gives_a_different_layout() {
+    let mut first = simulate(42, 0);
+    let mut second = simulate(43, 0);
+
+    let first = first.sheep_positions();
+    let second = second.sheep_positions();
+    assert_eq!(first.len(), second.len());
+    assert_ne!(first, second);
+}
@@ -0,0 +1,34 @@
+use bevy::prelude::*;
+use rusty_sheep::{Level, Terrain, TrialCourse};
+
+#[test]
+fn trial_level_paints_its_terrain() {
+    let bytes = std::fs::read("assets/levels/trial.tmj").unwrap();
+    let level = Level::from_tiled_json(&bytes).unwrap();
+
+    let terrain = level.terrain.expect("the trial level has a terrain layer");
+    assert_eq!(terrain.len(), level.columns * level.rows);
+    for kind in Terrain::ALL {
+        assert!(terrain.contains(&kind), "no {:?} on the trial field", kind);
+    }
+
+    assert_eq!(level.pens.len(), 1);
+    assert_eq!(level.pens[0].gates.len(), 1);
+}
+
+#[test]
+fn trial_level_lays_out_its_course() {
+    let bytes = std::fs::read("assets/levels/trial.tmj").unwrap();
+    let level = Level::from_tiled_json(&bytes).unwrap();
+
+    let course = TrialCourse::from_level(&level).expect("the trial level draws its course");
+    assert_eq!(
+        course.drive_gates,
+        vec![
+            (Vec2::new(-650., -350.), Vec2::new(-650., -150.)),
+            (Vec2::new(-100., 150.), Vec2::new(100., 150.)),
+        ]
+    );
+    assert_eq!(course.shed_ring_centre, Vec2::new(250., 350.));
+    assert_eq!(course.shed_ring_radius, 150.);
+}
@@ -0,0 +1,57 @@
+use bevy::prelude::*;
+use rusty_sheep::{GateCommand, HeadlessConfig, HeadlessSimulation, Level, LevelPen, PenProgress};
+
+// A bare field with the flock starting inside a pen, its gateway in the middle of the bottom fence
+fn penned_flock(sheep_count: i32) -> HeadlessSimulation {
+    let level = Level {
+        id: String::new(),
+        width: 2048.,
+        height: 2048.,
+        tile_size: 64.,
+        columns: 32,
+        rows: 32,
+        terrain: None,
+        obstacles: Vec::new(),
+        pens: vec![LevelPen {
+            polygon: vec![
+                Vec2::new(-200., -200.),
+                Vec2::new(200., -200.),
+                Vec2::new(200., 200.),
+                Vec2::new(-200., 200.),
+            ],
+            gates: vec![vec![
+                Vec2::new(-40., -206.),
+                Vec2::new(40., -206.),
+                Vec2::new(40., -194.),
+                Vec2::new(-40., -194.),
+            ]],
+        }],
+        handler_post: Some(Vec2::new(0., -800.)),
+        dog_start: Some(Vec2::new(0., -800.)),
+        sheep_spawns: vec![Rect::new(-150., -150., 150., 150.)],
+        drive_gates: Vec::new(),
+        shed_ring: None,
+    };
+
+    HeadlessSimulation::new(HeadlessConfig {
+        seed: 9,
+        sheep_count,
+        level: Some(level),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn a_shut_pen_holds_the_flock() {
+    let mut simulation = penned_flock(40);
+    simulation.world_mut().send_event(GateCommand::Close);
+
+    // The dog flanks round the pen the whole time, pushing the sheep into its fences
+    for _ in 0..20 {
+        simulation.run(60);
+        let progress = simulation.world().resource::<PenProgress>();
+        assert_eq!(progress.penned_count(), 40, "at tick {}", simulation.tick());
+        assert_eq!(progress.escapes, 0);
+    }
+    assert!(simulation.world().resource::<PenProgress>().complete);
+}
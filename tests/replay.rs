@@ -0,0 +1,73 @@
+use rusty_sheep::{
+    FlockingModelKind, GameMode, Gate, GateCommand, Handler, HeadlessConfig, HeadlessSimulation, Level, ReplayInput,
+};
+
+#[test]
+fn a_replay_brings_back_its_level_and_settings() {
+    let mut simulation = HeadlessSimulation::new(HeadlessConfig {
+        seed: 7,
+        sheep_count: 30,
+        flocking_model: FlockingModelKind::Couzin,
+        mode: GameMode::Trial,
+        level: Some(Level::load("levels/trial.tmj").unwrap()),
+        ..Default::default()
+    });
+    simulation.run(10);
+
+    let replay = simulation.recording().unwrap().clone();
+    assert_eq!(replay.level.as_deref(), Some("levels/trial.tmj"));
+    // The model picked over the params' own is the one kept
+    assert_eq!(replay.flocking_params.model, FlockingModelKind::Couzin);
+
+    let config = HeadlessConfig::from_replay(replay).unwrap();
+    assert_eq!(config.seed, 7);
+    assert_eq!(config.sheep_count, 30);
+    assert_eq!(config.flocking_model, FlockingModelKind::Couzin);
+    assert_eq!(config.mode, GameMode::Trial);
+    assert_eq!(config.level.map(|level| level.id).as_deref(), Some("levels/trial.tmj"));
+}
+
+#[test]
+fn a_replay_without_its_level_is_refused() {
+    let mut simulation = HeadlessSimulation::new(HeadlessConfig {
+        level: Some(Level::load("levels/trial.tmj").unwrap()),
+        ..Default::default()
+    });
+    simulation.run(1);
+
+    let mut replay = simulation.recording().unwrap().clone();
+    replay.level = Some("levels/missing.tmj".to_string());
+    assert!(HeadlessConfig::from_replay(replay).is_err());
+}
+
+#[test]
+fn a_recorded_run_plays_back_exactly() {
+    // The computer handles the dog, and the gate is shut and opened again along the way
+    let mut recorded = HeadlessSimulation::new(HeadlessConfig {
+        seed: 11,
+        sheep_count: 40,
+        handler: Handler::Ai,
+        level: Some(Level::load("levels/trial.tmj").unwrap()),
+        ..Default::default()
+    });
+    recorded.run(200);
+    recorded.world_mut().send_event(GateCommand::Close);
+    recorded.run(200);
+    recorded.world_mut().send_event(GateCommand::Toggle);
+    recorded.run(200);
+
+    let replay = recorded.recording().unwrap().clone();
+    assert!(replay.inputs.iter().any(|entry| matches!(entry.input, ReplayInput::Dog { .. })));
+    assert_eq!(replay.inputs.iter().filter(|entry| matches!(entry.input, ReplayInput::Gates(_))).count(), 2);
+
+    let mut replayed = HeadlessSimulation::new(HeadlessConfig::from_replay(replay).unwrap());
+    replayed.run(200);
+    assert!(replayed.world_mut().query::<&Gate>().iter(replayed.world()).all(|gate| gate.open));
+    replayed.run(200);
+    assert!(replayed.world_mut().query::<&Gate>().iter(replayed.world()).all(|gate| !gate.open));
+    replayed.run(200);
+
+    assert_eq!(replayed.tick(), recorded.tick());
+    assert_eq!(replayed.sheep_positions(), recorded.sheep_positions());
+    assert!(replayed.recording().is_none());
+}
@@ -0,0 +1,51 @@
+use rusty_sheep::{recognise_wav, DogCommand, WhistleTemplates};
+
+// One recording of each standard whistle, slightly off in timing and some in another key
+const WHISTLES: [(&str, DogCommand); 10] = [
+    ("lie_down", DogCommand::LayDown),
+    ("come_bye", DogCommand::ComeBye),
+    ("away", DogCommand::Away),
+    ("walk_on", DogCommand::WalkOn),
+    ("easy", DogCommand::Easy),
+    ("steady", DogCommand::Steady),
+    ("thatll_do", DogCommand::ThatllDo),
+    ("look_back", DogCommand::LookBack),
+    ("get_out", DogCommand::GetOut),
+    ("here", DogCommand::Here),
+];
+
+fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{}.wav", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+#[test]
+fn every_standard_whistle_is_recognised() {
+    let templates = WhistleTemplates::default();
+    for (name, command) in WHISTLES {
+        let heard = recognise_wav(fixture(&format!("whistle_{}", name)), &templates).unwrap();
+        assert_eq!(heard, vec![command], "whistle_{}.wav", name);
+    }
+}
+
+#[test]
+fn silence_and_noise_are_not_commands() {
+    let templates = WhistleTemplates::default();
+    for name in ["silence", "noise"] {
+        let heard = recognise_wav(fixture(name), &templates).unwrap();
+        assert!(heard.is_empty(), "{}.wav was heard as {:?}", name, heard);
+    }
+}
+
+#[test]
+fn whistles_can_be_taught_in_ron() {
+    // A handler whose long flat whistle means "Come bye" rather than "Lie down"
+    let templates: WhistleTemplates = ron::from_str(
+        "([
+            (command: ComeBye, notes: [(start: 2200, end: 2200, length: 0.8)]),
+        ])",
+    )
+    .unwrap();
+
+    assert_eq!(recognise_wav(fixture("whistle_lie_down"), &templates).unwrap(), vec![DogCommand::ComeBye]);
+    assert!(recognise_wav(fixture("whistle_come_bye"), &templates).unwrap().is_empty());
+}